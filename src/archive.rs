@@ -0,0 +1,127 @@
+use std::error::Error;
+use std::io::{Read, Seek, Write};
+use tracing::warn;
+use zip::write::SimpleFileOptions;
+use zip::{CompressionMethod, ZipArchive, ZipWriter};
+
+/// A relative path without empty, `.`, `..` or drive letter (`C:`) components, so extracting the
+/// archive can't write outside of the target directory.
+fn is_safe_path(path: &str) -> bool {
+    path.split(['/', '\\']).all(|c| {
+        let drive =
+            c.len() >= 2 && c.as_bytes()[0].is_ascii_alphabetic() && c.as_bytes()[1] == b':';
+        !c.is_empty() && c != "." && c != ".." && !drive
+    })
+}
+
+/// `\img\a.png` -> `img/a.png`, the leading backslash of MDict resource keys is removed.
+/// `None` if the path isn't safe to extract, see `is_safe_path`.
+pub fn key_to_path(key: &str) -> Option<String> {
+    let path = key.trim_start_matches(['\\', '/']).replace('\\', "/");
+    is_safe_path(&path).then_some(path)
+}
+
+/// `img/a.png` -> `\img\a.png`, the reverse of `key_to_path`.
+pub fn path_to_key(path: &str) -> String {
+    format!("\\{}", path.trim_start_matches('/').replace('/', "\\"))
+}
+
+/// Writes resources as files of a ZIP archive, paths mirror the resource keys.
+pub struct ResourceZipWriter<W: Write + Seek> {
+    zip: ZipWriter<W>,
+    count: u64,
+}
+
+impl<W: Write + Seek> ResourceZipWriter<W> {
+    pub fn new(writer: W) -> Self {
+        Self {
+            zip: ZipWriter::new(writer),
+            count: 0,
+        }
+    }
+
+    /// Resources with an unsafe path or a path already in the archive are skipped.
+    pub fn add(&mut self, key: &str, value: &[u8]) -> Result<(), Box<dyn Error>> {
+        let path = match key_to_path(key) {
+            Some(v) => v,
+            None => {
+                warn!(key, "invalid resource path, skipped");
+                return Ok(());
+            }
+        };
+        let options = SimpleFileOptions::default().compression_method(CompressionMethod::Deflated);
+        if let Err(e) = self.zip.start_file(path.as_str(), options) {
+            warn!(key, error = %e, "resource skipped");
+            return Ok(());
+        }
+        self.zip.write_all(value)?;
+        self.count += 1;
+        Ok(())
+    }
+
+    /// Finish the archive and return the number of written resources.
+    pub fn finish(self) -> Result<u64, Box<dyn Error>> {
+        self.zip.finish()?;
+        Ok(self.count)
+    }
+}
+
+/// Visit every file of a ZIP archive as a resource key and its content, directories and files
+/// with unsafe paths are skipped.
+pub fn read_resource_zip(
+    reader: impl Read + Seek,
+    f: &mut impl FnMut(String, Vec<u8>),
+) -> Result<u64, Box<dyn Error>> {
+    let mut zip = ZipArchive::new(reader)?;
+    let mut count = 0;
+    for i in 0..zip.len() {
+        let mut file = zip.by_index(i)?;
+        if file.is_dir() {
+            continue;
+        }
+        if !is_safe_path(file.name()) {
+            warn!(path = file.name(), "invalid resource path, skipped");
+            continue;
+        }
+        let mut data = Vec::with_capacity(file.size() as usize);
+        file.read_to_end(&mut data)?;
+        f(path_to_key(file.name()), data);
+        count += 1;
+    }
+    Ok(count)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn resource_paths() {
+        assert_eq!(key_to_path("\\img\\a.png").as_deref(), Some("img/a.png"));
+        assert_eq!(
+            key_to_path("/css/style.css").as_deref(),
+            Some("css/style.css")
+        );
+        assert_eq!(path_to_key("img/a.png"), "\\img\\a.png");
+    }
+
+    #[test]
+    fn unsafe_paths() {
+        for key in [
+            "\\..\\..\\evil.sh",
+            "\\img\\..\\a.png",
+            "\\.\\a.png",
+            "\\img\\\\a.png",
+            "\\img\\",
+            "\\",
+            "\\C:\\evil.sh",
+            "\\img\\c:evil.sh",
+        ] {
+            assert_eq!(key_to_path(key), None, "{}", key);
+        }
+        assert!(!is_safe_path("/etc/passwd"));
+        assert!(!is_safe_path("../evil.sh"));
+        assert!(!is_safe_path("img\\..\\..\\evil.sh"));
+        assert!(is_safe_path("img/a.b.png"));
+    }
+}
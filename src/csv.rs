@@ -0,0 +1,73 @@
+use crate::raw::RawDict;
+use crate::utils::strip_html;
+use std::error::Error;
+use std::io::{self, Write};
+
+/// Writes RFC 4180 records, fields are quoted when they contain the delimiter, quotes or line
+/// breaks.
+pub struct CsvWriter<W: Write> {
+    writer: W,
+    delimiter: char,
+}
+
+impl<W: Write> CsvWriter<W> {
+    pub fn new(writer: W) -> Self {
+        Self::with_delimiter(writer, ',')
+    }
+
+    pub fn with_delimiter(writer: W, delimiter: char) -> Self {
+        Self { writer, delimiter }
+    }
+
+    pub fn write_record<S: AsRef<str>>(&mut self, fields: &[S]) -> io::Result<()> {
+        for (i, field) in fields.iter().enumerate() {
+            if i > 0 {
+                write!(self.writer, "{}", self.delimiter)?;
+            }
+            let field = field.as_ref();
+            if field.contains([self.delimiter, '"', '\r', '\n']) {
+                write!(self.writer, "\"{}\"", field.replace('"', "\"\""))?;
+            } else {
+                self.writer.write_all(field.as_bytes())?;
+            }
+        }
+        self.writer.write_all(b"\r\n")
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+}
+
+/// Write `key,text` records of an entry dictionary, text is stripped of HTML. With
+/// `max_value_bytes`, HTML is truncated to at most that many bytes before stripping.
+pub fn raw_to_csv(
+    dict: &RawDict,
+    writer: impl Write,
+    max_value_bytes: Option<usize>,
+) -> Result<u64, Box<dyn Error>> {
+    let mut csv = CsvWriter::new(writer);
+    csv.write_record(&["key", "text"])?;
+    let mut count = 0;
+    let mut error = None;
+    dict.traverse_entry(&mut |name: &str, value: &[u8]| {
+        if error.is_some() {
+            return;
+        }
+        let mut html = String::from_utf8_lossy(value);
+        if let Some(max) = max_value_bytes.filter(|&v| v < html.len()) {
+            let mut end = max;
+            while !html.is_char_boundary(end) {
+                end -= 1;
+            }
+            html = html[..end].to_string().into();
+        }
+        error = csv.write_record(&[name, &strip_html(&html)]).err();
+        count += 1;
+    })?;
+    if let Some(e) = error {
+        return Err(e.into());
+    }
+    csv.flush()?;
+    Ok(count)
+}
@@ -0,0 +1,73 @@
+use base64::prelude::{Engine, BASE64_STANDARD};
+use beluga_core::beluga::{BelFileType, Beluga, Metadata};
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use std::io::{BufRead, Write};
+
+#[derive(Serialize)]
+struct Line<'a> {
+    key: &'a str,
+    value: &'a str,
+}
+
+#[derive(Deserialize)]
+struct OwnedLine {
+    key: String,
+    value: String,
+}
+
+/// Writes one `{"key": "...", "value": "..."}` object per line.
+/// Values of resource dictionaries are base64 encoded.
+pub struct JsonlWriter<W: Write> {
+    writer: W,
+    file_type: BelFileType,
+    count: u64,
+}
+
+impl<W: Write> JsonlWriter<W> {
+    pub fn new(writer: W, file_type: BelFileType) -> Self {
+        Self {
+            writer,
+            file_type,
+            count: 0,
+        }
+    }
+
+    pub fn write_entry(&mut self, key: &str, value: &[u8]) -> Result<(), Box<dyn Error>> {
+        let value = match self.file_type {
+            BelFileType::Entry => String::from_utf8_lossy(value).to_string(),
+            BelFileType::Resource => BASE64_STANDARD.encode(value),
+        };
+        serde_json::to_writer(&mut self.writer, &Line { key, value: &value })?;
+        self.writer.write_all(b"\n")?;
+        self.count += 1;
+        Ok(())
+    }
+
+    /// Flush and return the number of written entries.
+    pub fn finish(mut self) -> Result<u64, Box<dyn Error>> {
+        self.writer.flush()?;
+        Ok(self.count)
+    }
+}
+
+/// Build a Beluga dictionary from JSON lines written by [`JsonlWriter`], empty lines are skipped.
+pub fn read_jsonl(reader: impl BufRead, file_type: BelFileType) -> Result<Beluga, Box<dyn Error>> {
+    let mut dict = Beluga::new(Metadata::new(), file_type);
+    for (i, line) in reader.lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let line: OwnedLine =
+            serde_json::from_str(&line).map_err(|e| format!("Line {}: {}", i + 1, e))?;
+        let value = match file_type {
+            BelFileType::Entry => line.value.into_bytes(),
+            BelFileType::Resource => BASE64_STANDARD
+                .decode(line.value)
+                .map_err(|e| format!("Line {}: {}", i + 1, e))?,
+        };
+        dict.input_entry(line.key, value);
+    }
+    Ok(dict)
+}
@@ -1,33 +1,871 @@
+use archive::{read_resource_zip, ResourceZipWriter};
 use beluga_core::beluga::*;
-use clap::{Arg, Command};
+use clap::builder::ArgPredicate;
+use clap::parser::ValueSource;
+use clap::{Arg, ArgAction, ArgMatches, Command};
+use flate2::read::GzDecoder;
+use jsonl::{read_jsonl, JsonlWriter};
+use merge::Conflict;
 use pbr::ProgressBar;
-use raw::RawDict;
+use raw::{DiffKind, KeyCollation, RawDict, RawOptions, RepairStrategy};
+use regex::Regex;
+use schema::EntrySchema;
+use scraper::Selector;
+use stardict::StarDict;
+use std::error::Error;
+use std::fs::{self, File};
+use std::io::{BufReader, BufWriter, IsTerminal};
 use std::path::Path;
+use std::process;
+use tracing::{error, info, warn};
+use tracing_subscriber::EnvFilter;
 
+mod archive;
+mod csv;
+mod jsonl;
+mod merge;
 mod raw;
+mod schema;
+mod stardict;
+mod utils;
+mod validate;
+mod wikitext;
+
+fn input_arg() -> Arg {
+    Arg::new("input")
+        .short('i')
+        .num_args(1)
+        .value_name("SOURCE")
+        .help("Source file")
+        .required(true)
+}
+
+fn output_arg() -> Arg {
+    Arg::new("output")
+        .short('o')
+        .num_args(1)
+        .value_name("TARGET")
+        .help("Target file")
+        .required(true)
+}
+
+fn raw_option_args() -> Vec<Arg> {
+    vec![
+        Arg::new("key-collation")
+            .long("key-collation")
+            .num_args(1)
+            .value_name("COLLATION")
+            .value_parser(["binary", "nocase"])
+            .default_value("binary")
+            .help("Collation of entry names of `.bel-db` or `.beld-db`, nocase makes names that only differ in ASCII case duplicates"),
+        Arg::new("sqlite-cache-kb")
+            .long("sqlite-cache-kb")
+            .num_args(1)
+            .value_name("KB")
+            .value_parser(clap::value_parser!(u32))
+            .default_value("65536")
+            .help("SQLite page cache size of `.bel-db` or `.beld-db`"),
+        Arg::new("wal")
+            .long("wal")
+            .num_args(1)
+            .value_name("MODE")
+            .value_parser(["on", "off"])
+            .default_value("on")
+            .help("Write `.bel-db` or `.beld-db` in WAL journal mode"),
+        Arg::new("fts")
+            .long("fts")
+            .action(ArgAction::SetTrue)
+            .help("Create an FTS5 index of entries in `.bel-db` for the search subcommand"),
+        Arg::new("batch-size")
+            .long("batch-size")
+            .num_args(1)
+            .value_name("N")
+            .value_parser(clap::builder::RangedU64ValueParser::<usize>::new().range(1..))
+            .default_value("200")
+            .help("Entries written to `.bel-db` or `.beld-db` per transaction")
+            .long_help(
+                "Entries written to `.bel-db` or `.beld-db` per transaction. 500 to 5000 is \
+                recommended for fast disks, memory grows with N times the average entry size.",
+            ),
+        Arg::new("fingerprint")
+            .long("fingerprint")
+            .action(ArgAction::SetTrue)
+            .help("Store SHA-256 hashes of entries in `.bel-db` or `.beld-db` for the diff subcommand"),
+    ]
+}
+
+fn raw_options(matches: &ArgMatches) -> RawOptions {
+    let key_collation = match matches
+        .get_one::<String>("key-collation")
+        .map(|v| v.as_str())
+    {
+        Some("nocase") => KeyCollation::NoCase,
+        _ => KeyCollation::Binary,
+    };
+    RawOptions {
+        key_collation,
+        cache_kb: *matches
+            .get_one::<u32>("sqlite-cache-kb")
+            .expect("no cache size"),
+        wal: matches.get_one::<String>("wal").map(|v| v.as_str()) != Some("off"),
+        fts: matches.get_flag("fts"),
+        batch_size: *matches
+            .get_one::<usize>("batch-size")
+            .expect("no batch size"),
+        fingerprint: matches.get_flag("fingerprint"),
+    }
+}
 
 #[tokio::main]
 async fn main() {
+    // logs go to stderr so they don't interleave with progress bars and results on stdout
+    tracing_subscriber::fmt()
+        .with_env_filter(
+            EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("info")),
+        )
+        .with_target(false)
+        .with_ansi(std::io::stderr().is_terminal())
+        .with_writer(std::io::stderr)
+        .init();
     let matches = Command::new("Beluga Dictionary Builder")
         .version("0.2.0")
         .about("Transform dictionary format. `.bel-db` <-> `.bel`, `.beld-db` <->`.beld`")
+        .args_conflicts_with_subcommands(true)
+        .subcommand_negates_reqs(true)
+        .arg(input_arg())
+        .arg(
+            output_arg()
+                .required(false)
+                .required_unless_present("migrate"),
+        )
+        .arg(
+            Arg::new("migrate")
+                .long("migrate")
+                .action(ArgAction::SetTrue)
+                .conflicts_with("output")
+                .help("Add the v2 `schema_info` and `token_entry` tables to `.bel-db` or `.beld-db` in place")
+                .long_help(
+                    "Add the v2 `schema_info` and `token_entry` tables to `.bel-db` or \
+                    `.beld-db` in place. `token_entry` is a snapshot of the `token` table, \
+                    conversions don't read or update it.",
+                ),
+        )
+        .arg(
+            Arg::new("entry-dedup-hash")
+                .long("entry-dedup-hash")
+                .action(ArgAction::SetTrue)
+                .help("Save entries with an already seen definition as links to the first one"),
+        )
+        .arg(
+            Arg::new("entry-format")
+                .long("entry-format")
+                .num_args(1)
+                .value_name("FORMAT")
+                .value_parser(["html", "json"])
+                .default_value("html")
+                .default_value_if("entry-schema", ArgPredicate::IsPresent, "json")
+                .help("Format of entry values in `.bel`, json is `{\"html\": \"...\", ...}`"),
+        )
+        .arg(
+            Arg::new("entry-schema")
+                .long("entry-schema")
+                .num_args(1)
+                .value_name("FILE")
+                .help(
+                    "TOML file of fields extracted from HTML by CSS selectors, implies json entry format",
+                ),
+        )
+        .arg(
+            Arg::new("filter")
+                .long("filter")
+                .num_args(1)
+                .value_name("REGEX")
+                .value_parser(Regex::new)
+                .help("Only convert entries whose names match the regex")
+                .long_help(
+                    "Only convert entries whose names match the regex. Names are matched before \
+                    definitions are read, so skipped entries cost almost nothing.",
+                ),
+        )
         .arg(
-            Arg::new("input")
-                .short('i')
+            Arg::new("max-value-bytes")
+                .long("max-value-bytes")
                 .num_args(1)
-                .value_name("SOURCE")
-                .help("Source file")
-                .required(true),
+                .value_name("N")
+                .value_parser(clap::value_parser!(usize))
+                .help("Truncate HTML of entries to N bytes before stripping (csv)"),
         )
         .arg(
-            Arg::new("output")
-                .short('o')
+            Arg::new("offset")
+                .long("offset")
                 .num_args(1)
-                .value_name("TARGET")
-                .help("Target file")
-                .required(true),
+                .value_name("M")
+                .value_parser(clap::value_parser!(u64))
+                .default_value("0")
+                .help("Skip the first M entries (after --filter)"),
+        )
+        .arg(
+            Arg::new("limit")
+                .long("limit")
+                .num_args(1)
+                .value_name("N")
+                .value_parser(clap::value_parser!(u64))
+                .help("Convert at most N entries (after --filter)"),
+        )
+        .args(raw_option_args())
+        .subcommand(
+            Command::new("export")
+                .about("Export `.bel-db` to other dictionary formats")
+                .arg(input_arg())
+                .arg(output_arg())
+                .arg(
+                    Arg::new("format")
+                        .short('f')
+                        .long("format")
+                        .num_args(1)
+                        .value_name("FORMAT")
+                        .value_parser([
+                            "fts",
+                            "vocabulary",
+                            "plaintext",
+                            "cedict",
+                            "opds",
+                            "mediawiki",
+                            "initials",
+                            "lingualeo",
+                            "xdxf",
+                            "epub",
+                            "csv",
+                        ])
+                        .help("Target format")
+                        .required(true),
+                )
+                .arg(
+                    Arg::new("title")
+                        .long("title")
+                        .num_args(1)
+                        .value_name("TITLE")
+                        .help("Dictionary title, defaults to the source file name"),
+                )
+                .arg(
+                    Arg::new("language")
+                        .long("language")
+                        .num_args(1)
+                        .value_name("LANG")
+                        .default_value("en")
+                        .help("Dictionary language code"),
+                )
+                .arg(
+                    Arg::new("target-language")
+                        .long("target-language")
+                        .num_args(1)
+                        .value_name("LANG")
+                        .help("Language code of definitions (xdxf, epub), epub defaults to --language"),
+                )
+                .arg(
+                    Arg::new("exclude-multiword")
+                        .long("exclude-multiword")
+                        .action(ArgAction::SetTrue)
+                        .help("Skip entry names containing spaces (vocabulary)"),
+                )
+                .arg(
+                    Arg::new("cedict-traditional-col")
+                        .long("cedict-traditional-col")
+                        .num_args(1)
+                        .value_name("COLUMN")
+                        .help("Entry table column of traditional characters (cedict)"),
+                )
+                .arg(
+                    Arg::new("cedict-pinyin-col")
+                        .long("cedict-pinyin-col")
+                        .num_args(1)
+                        .value_name("COLUMN")
+                        .default_value("pinyin")
+                        .help("Entry table column of pinyin (cedict)"),
+                )
+                .arg(
+                    Arg::new("base-url")
+                        .long("base-url")
+                        .num_args(1)
+                        .value_name("URL")
+                        .help("Base URL of entry links (opds)"),
+                )
+                .arg(
+                    Arg::new("schema")
+                        .long("schema")
+                        .num_args(1)
+                        .value_name("FILE")
+                        .help("TOML file of columns and transformations (csv)"),
+                ),
+        )
+        .subcommand(
+            Command::new("import")
+                .about("Import other dictionary formats into `.bel-db`")
+                .arg(input_arg())
+                .arg(output_arg())
+                .arg(
+                    Arg::new("format")
+                        .short('f')
+                        .long("format")
+                        .num_args(1)
+                        .value_name("FORMAT")
+                        .value_parser(["open-russian", "freedict", "jmdict", "ods", "wiktionary"])
+                        .help("Source format")
+                        .required(true),
+                )
+                .args(raw_option_args()),
+        )
+        .subcommand(
+            Command::new("check")
+                .about("Report problems of `.bel-db` or `.beld-db`")
+                .arg(input_arg()),
+        )
+        .subcommand(
+            Command::new("repair")
+                .about("Fix or enrich `.bel-db` or `.beld-db` in place")
+                .arg(input_arg())
+                .arg(
+                    Arg::new("utf8")
+                        .long("utf8")
+                        .action(ArgAction::SetTrue)
+                        .help("Replace invalid UTF-8 sequences of text entries"),
+                )
+                .arg(
+                    Arg::new("strategy")
+                        .long("strategy")
+                        .num_args(1)
+                        .value_name("STRATEGY")
+                        .value_parser(["fix-mojibake", "strip-non-utf8"])
+                        .help("Repair encoding problems of text entries"),
+                )
+                .arg(
+                    Arg::new("remove-null")
+                        .long("remove-null")
+                        .action(ArgAction::SetTrue)
+                        .help("Delete entries without text and binary"),
+                )
+                .arg(
+                    Arg::new("phonetics-selector")
+                        .long("phonetics-selector")
+                        .num_args(1)
+                        .value_name("SELECTOR")
+                        .help(
+                            "Save the text matching the CSS selector into the `phonetics` column",
+                        ),
+                ),
+        )
+        .subcommand(
+            Command::new("merge")
+                .about("Merge dictionaries of the same file type into one")
+                .arg(
+                    Arg::new("input")
+                        .short('i')
+                        .num_args(1)
+                        .value_name("SOURCE")
+                        .action(ArgAction::Append)
+                        .help("Source file, can be given multiple times")
+                        .required(true),
+                )
+                .arg(output_arg())
+                .arg(
+                    Arg::new("conflict")
+                        .long("conflict")
+                        .num_args(1)
+                        .value_name("STRATEGY")
+                        .value_parser(["first", "last"])
+                        .default_value("last")
+                        .help("Which definition is kept for entries in several sources"),
+                )
+                .args(raw_option_args()),
+        )
+        .subcommand(
+            Command::new("validate")
+                .about("Check the integrity of `.bel` or `.beld`")
+                .arg(input_arg())
+                .arg(
+                    Arg::new("strict")
+                        .long("strict")
+                        .action(ArgAction::SetTrue)
+                        .help("Also require well-formed HTML of text entries"),
+                ),
+        )
+        .subcommand(
+            Command::new("search")
+                .about("Search entries of `.bel-db` by name")
+                .arg(input_arg())
+                .arg(
+                    Arg::new("query")
+                        .short('q')
+                        .num_args(1)
+                        .value_name("QUERY")
+                        .help("Part of entry names")
+                        .required(true),
+                ),
+        )
+        .subcommand(
+            Command::new("diff")
+                .about("List entries added, removed or changed between two fingerprinted raw files")
+                .arg(input_arg().help("Old file"))
+                .arg(
+                    Arg::new("new")
+                        .short('n')
+                        .long("new")
+                        .num_args(1)
+                        .value_name("FILE")
+                        .help("New file")
+                        .required(true),
+                ),
+        )
+        .subcommand(
+            Command::new("info")
+                .about("Print metadata of Beluga or raw dictionaries as JSON")
+                .arg(input_arg()),
         )
         .get_matches();
+    match matches.subcommand() {
+        Some(("export", sub_matches)) => {
+            if let Err(e) = export(sub_matches) {
+                error!("{}", e);
+                process::exit(1);
+            }
+        }
+        Some(("import", sub_matches)) => {
+            if let Err(e) = import(sub_matches) {
+                error!("{}", e);
+                process::exit(1);
+            }
+        }
+        Some(("check", sub_matches)) => {
+            if let Err(e) = check(sub_matches) {
+                error!("{}", e);
+                process::exit(1);
+            }
+        }
+        Some(("repair", sub_matches)) => {
+            if let Err(e) = repair(sub_matches) {
+                error!("{}", e);
+                process::exit(1);
+            }
+        }
+        Some(("merge", sub_matches)) => {
+            if let Err(e) = merge(sub_matches).await {
+                error!("{}", e);
+                process::exit(1);
+            }
+        }
+        Some(("validate", sub_matches)) => {
+            if let Err(e) = validate(sub_matches).await {
+                error!("{}", e);
+                process::exit(1);
+            }
+        }
+        Some(("search", sub_matches)) => {
+            if let Err(e) = search(sub_matches) {
+                error!("{}", e);
+                process::exit(1);
+            }
+        }
+        Some(("diff", sub_matches)) => {
+            if let Err(e) = diff(sub_matches) {
+                error!("{}", e);
+                process::exit(1);
+            }
+        }
+        Some(("info", sub_matches)) => {
+            if let Err(e) = info(sub_matches).await {
+                error!("{}", e);
+                process::exit(1);
+            }
+        }
+        _ if matches.get_flag("migrate") => {
+            if let Err(e) = migrate(&matches) {
+                error!("{}", e);
+                process::exit(1);
+            }
+        }
+        _ => transform(&matches).await,
+    }
+}
+
+/// Whether `id` was given on the command line rather than taken from its default.
+fn is_given(matches: &ArgMatches, id: &str) -> bool {
+    matches.value_source(id) == Some(ValueSource::CommandLine)
+}
+
+/// Err if a raw option is given but `target_ext` isn't a raw dictionary created with it,
+/// --fts only applies to `.bel-db`.
+fn ensure_raw_options_used(matches: &ArgMatches, target_ext: &str) -> Result<(), String> {
+    let raw = target_ext == EXT_RAW_ENTRY || target_ext == EXT_RAW_RESOURCE;
+    // (flag, whether the target is created with it)
+    let raw_flags: &[(&str, bool)] = &[
+        ("key-collation", raw),
+        ("sqlite-cache-kb", raw),
+        ("wal", raw),
+        ("fts", target_ext == EXT_RAW_ENTRY),
+        ("batch-size", raw),
+        ("fingerprint", raw),
+    ];
+    for &(id, used) in raw_flags {
+        if !used && is_given(matches, id) {
+            return Err(format!(
+                "--{} is not supported for `.{}` targets",
+                id, target_ext
+            ));
+        }
+    }
+    Ok(())
+}
+
+fn filter_arg(matches: &ArgMatches) -> Option<Regex> {
+    matches.get_one::<Regex>("filter").cloned()
+}
+
+fn load_entry_schema(path: &str) -> Result<EntrySchema, Box<dyn Error>> {
+    EntrySchema::from_toml(&fs::read_to_string(path)?)
+}
+
+fn ensure_raw_file(filepath: &str) -> Result<(), Box<dyn Error>> {
+    if !(filepath.ends_with(EXT_RAW_ENTRY) || filepath.ends_with(EXT_RAW_RESOURCE)) {
+        return Err(format!(
+            "Invalid input file, `.{}` or `.{}` is required",
+            EXT_RAW_ENTRY, EXT_RAW_RESOURCE
+        )
+        .into());
+    }
+    Ok(())
+}
+
+fn export(matches: &ArgMatches) -> Result<(), Box<dyn Error>> {
+    let source: &String = matches.get_one("input").expect("no source file");
+    let target: &String = matches.get_one("output").expect("no target file");
+    let format: &String = matches.get_one("format").expect("no target format");
+    ensure_raw_file(source)?;
+    // (flag, formats using it)
+    let format_flags: &[(&str, &[&str])] = &[
+        ("exclude-multiword", &["vocabulary"]),
+        ("cedict-traditional-col", &["cedict"]),
+        ("cedict-pinyin-col", &["cedict"]),
+        ("title", &["opds", "xdxf", "epub"]),
+        ("language", &["xdxf", "epub"]),
+        ("target-language", &["xdxf", "epub"]),
+        ("base-url", &["opds"]),
+        ("schema", &["csv"]),
+    ];
+    for &(id, formats) in format_flags {
+        if is_given(matches, id) && !formats.contains(&format.as_str()) {
+            return Err(format!("--{} is not supported for {}", id, format).into());
+        }
+    }
+    let title = match matches.get_one::<String>("title") {
+        Some(v) => v.clone(),
+        None => Path::new(source)
+            .file_stem()
+            .and_then(|v| v.to_str())
+            .unwrap_or_default()
+            .to_string(),
+    };
+    let language: &String = matches.get_one("language").expect("no language");
+    let dict = RawDict::from(source);
+    match format.as_str() {
+        "fts" => dict.export_to_sqlite_fts(target),
+        "vocabulary" => {
+            let count = dict.export_vocabulary_list(
+                BufWriter::new(File::create(target)?),
+                matches.get_flag("exclude-multiword"),
+            )?;
+            info!(count, "words exported");
+            Ok(())
+        }
+        "plaintext" => {
+            let count = dict.export_definitions_plaintext(BufWriter::new(File::create(target)?))?;
+            info!(count, "entries exported");
+            Ok(())
+        }
+        "cedict" => {
+            let count = dict.export_to_cedict(
+                BufWriter::new(File::create(target)?),
+                matches
+                    .get_one::<String>("cedict-traditional-col")
+                    .map(|v| v.as_str()),
+                matches
+                    .get_one::<String>("cedict-pinyin-col")
+                    .expect("no pinyin column"),
+            )?;
+            info!(count, "entries exported");
+            Ok(())
+        }
+        "opds" => {
+            let base_url: &String = matches
+                .get_one("base-url")
+                .ok_or("--base-url is required for opds")?;
+            dict.export_to_opds(base_url, &title, BufWriter::new(File::create(target)?))
+        }
+        "mediawiki" => {
+            let count = dict.export_to_wikisaurus(BufWriter::new(File::create(target)?))?;
+            info!(count, "pages exported");
+            Ok(())
+        }
+        "lingualeo" => {
+            let count = dict.export_to_lingualeo(BufWriter::new(File::create(target)?))?;
+            info!(count, "words exported");
+            Ok(())
+        }
+        "xdxf" => {
+            let target_language: &String = matches
+                .get_one("target-language")
+                .ok_or("--target-language is required for xdxf")?;
+            let count = dict.export_to_xdxf(
+                BufWriter::new(File::create(target)?),
+                &title,
+                language,
+                target_language,
+            )?;
+            info!(count, "entries exported");
+            Ok(())
+        }
+        "csv" => {
+            let schema: &String = matches
+                .get_one("schema")
+                .ok_or("--schema is required for csv")?;
+            let count =
+                dict.export_to_csv_with_schema(schema, BufWriter::new(File::create(target)?))?;
+            info!(count, "entries exported");
+            Ok(())
+        }
+        "epub" => dict.export_to_epub_dictionary(
+            target,
+            &title,
+            language,
+            matches
+                .get_one::<String>("target-language")
+                .unwrap_or(language),
+        ),
+        "initials" => {
+            let counts = dict.export_entries_by_initial_letter(target)?;
+            let mut counts: Vec<(char, u64)> = counts.into_iter().collect();
+            counts.sort();
+            for (initial, count) in counts {
+                println!("{}\t{}", initial, count);
+            }
+            Ok(())
+        }
+        _ => Err(format!("Unsupported format: {}", format).into()),
+    }
+}
+
+fn import(matches: &ArgMatches) -> Result<(), Box<dyn Error>> {
+    let source: &String = matches.get_one("input").expect("no source file");
+    let target: &String = matches.get_one("output").expect("no target file");
+    let format: &String = matches.get_one("format").expect("no source format");
+    if !target.ends_with(EXT_RAW_ENTRY) {
+        return Err(format!("Invalid target file, `.{}` is required", EXT_RAW_ENTRY).into());
+    }
+    let mut dict = RawDict::new_with_options(target, &raw_options(matches));
+    let count = match format.as_str() {
+        "open-russian" => dict.import_from_open_russian(BufReader::new(File::open(source)?))?,
+        "freedict" => dict.import_from_freedict(File::open(source)?)?,
+        "ods" => dict.import_from_ods(source)?,
+        "wiktionary" => dict.import_from_wiktionary_dump(File::open(source)?)?,
+        "jmdict" => {
+            let file = File::open(source)?;
+            if source.ends_with(".gz") {
+                dict.import_from_jmdict_xml(GzDecoder::new(file))?
+            } else {
+                dict.import_from_jmdict_xml(file)?
+            }
+        }
+        _ => return Err(format!("Unsupported format: {}", format).into()),
+    };
+    info!(count, "entries imported");
+    Ok(())
+}
+
+async fn merge(matches: &ArgMatches) -> Result<(), Box<dyn Error>> {
+    let sources: Vec<&str> = matches
+        .get_many::<String>("input")
+        .expect("no source file")
+        .map(|v| v.as_str())
+        .collect();
+    let target: &String = matches.get_one("output").expect("no target file");
+    ensure_raw_options_used(
+        matches,
+        Path::new(target)
+            .extension()
+            .and_then(|v| v.to_str())
+            .unwrap_or_default(),
+    )?;
+    let conflict = match matches.get_one::<String>("conflict").map(|v| v.as_str()) {
+        Some("first") => Conflict::First,
+        _ => Conflict::Last,
+    };
+    let count = merge::merge(&sources, target, conflict, &raw_options(matches)).await?;
+    info!(count, "entries merged");
+    Ok(())
+}
+
+async fn validate(matches: &ArgMatches) -> Result<(), Box<dyn Error>> {
+    let source: &String = matches.get_one("input").expect("no source file");
+    if !source.ends_with(EXT_ENTRY) && !source.ends_with(EXT_RESOURCE) {
+        return Err(format!(
+            "Invalid input file, `.{}` or `.{}` is required",
+            EXT_ENTRY, EXT_RESOURCE
+        )
+        .into());
+    }
+    let report = validate::validate(source, matches.get_flag("strict")).await;
+    println!(
+        "Entries: {} (metadata: {})",
+        report.entries, report.expected_entries
+    );
+    println!("Tokens: {}", report.tokens);
+    if report.aborted {
+        println!("Reading aborted, the file is corrupted");
+    }
+    println!("Empty keys: {}", report.empty_keys);
+    for (title, names) in [
+        ("Entries with invalid UTF-8", &report.invalid_utf8),
+        ("Entries with malformed HTML", &report.malformed_html),
+        ("Tokens with missing entries", &report.dangling_tokens),
+    ] {
+        println!("{}: {}", title, names.len());
+        for name in names {
+            println!("  {}", name);
+        }
+    }
+    if !report.is_ok() {
+        return Err("Validation failed".into());
+    }
+    Ok(())
+}
+
+fn search(matches: &ArgMatches) -> Result<(), Box<dyn Error>> {
+    let source: &String = matches.get_one("input").expect("no source file");
+    let query: &String = matches.get_one("query").expect("no query");
+    ensure_raw_file(source)?;
+    let dict = RawDict::from(source);
+    for (name, text) in dict.search_entry(query)? {
+        let mut text: String = text.split_whitespace().collect::<Vec<&str>>().join(" ");
+        if let Some((i, _)) = text.char_indices().nth(200) {
+            text.truncate(i);
+            text.push_str("...");
+        }
+        println!("{}\t{}", name, text);
+    }
+    Ok(())
+}
+
+fn diff(matches: &ArgMatches) -> Result<(), Box<dyn Error>> {
+    let old: &String = matches.get_one("input").expect("no source file");
+    let new: &String = matches.get_one("new").expect("no new file");
+    ensure_raw_file(old)?;
+    ensure_raw_file(new)?;
+    let diff = RawDict::from(old).diff(&RawDict::from(new))?;
+    for entry in &diff {
+        let mark = match entry.kind {
+            DiffKind::Added => '+',
+            DiffKind::Removed => '-',
+            DiffKind::Changed => '~',
+        };
+        println!("{} {}", mark, entry.name);
+    }
+    info!(count = diff.len(), "entries differ");
+    Ok(())
+}
+
+async fn info(matches: &ArgMatches) -> Result<(), Box<dyn Error>> {
+    let source: &String = matches.get_one("input").expect("no source file");
+    let type_name = |v: BelFileType| match v {
+        BelFileType::Entry => "entry",
+        BelFileType::Resource => "resource",
+    };
+    let info = if source.ends_with(EXT_RAW_ENTRY) || source.ends_with(EXT_RAW_RESOURCE) {
+        let dict = RawDict::from(source);
+        let file_type = if source.ends_with(EXT_RAW_ENTRY) {
+            BelFileType::Entry
+        } else {
+            BelFileType::Resource
+        };
+        serde_json::json!({
+            "format": "raw",
+            "file_type": type_name(file_type),
+            "schema_version": dict.schema_version()?,
+            "entry_num": dict.total_entries(),
+            "token_num": dict.total_tokens(),
+        })
+    } else if source.ends_with(EXT_ENTRY) || source.ends_with(EXT_RESOURCE) {
+        let dict = Beluga::from_file(source).await;
+        serde_json::json!({
+            "format": "beluga",
+            "file_type": type_name(dict.file_type),
+            "entry_num": dict.metadata.entry_num,
+        })
+    } else {
+        return Err("Invalid input file, a Beluga or raw dictionary is required".into());
+    };
+    println!("{}", serde_json::to_string_pretty(&info)?);
+    Ok(())
+}
+
+fn check(matches: &ArgMatches) -> Result<(), Box<dyn Error>> {
+    let source: &String = matches.get_one("input").expect("no source file");
+    ensure_raw_file(source)?;
+    let dict = RawDict::from(source);
+    let duplicates = dict.compute_entry_duplicates()?;
+    println!("Duplicate entries: {}", duplicates.len());
+    for name in duplicates {
+        println!("  {}", name);
+    }
+    if dict.total_tokens() > 0 {
+        let uncovered = dict.uncovered_entries()?;
+        println!("Entries without tokens: {}", uncovered.len());
+        for name in uncovered {
+            println!("  {}", name);
+        }
+    }
+    Ok(())
+}
+
+fn repair(matches: &ArgMatches) -> Result<(), Box<dyn Error>> {
+    let source: &String = matches.get_one("input").expect("no source file");
+    ensure_raw_file(source)?;
+    let mut dict = RawDict::from(source);
+    if matches.get_flag("utf8") {
+        let names = dict.enforce_utf8()?;
+        for name in &names {
+            warn!(entry = %name, "invalid UTF-8 replaced");
+        }
+        info!(count = names.len(), "entries with invalid UTF-8");
+    }
+    if let Some(strategy) = matches.get_one::<String>("strategy") {
+        let strategy = match strategy.as_str() {
+            "fix-mojibake" => RepairStrategy::FixMojibake,
+            _ => RepairStrategy::StripNonUtf8,
+        };
+        let count = dict.repair_entries(strategy)?;
+        info!(count, "entries repaired");
+    }
+    if matches.get_flag("remove-null") {
+        let count = dict.repair_null_values()?;
+        info!(count, "empty entries removed");
+    }
+    if let Some(selector) = matches.get_one::<String>("phonetics-selector") {
+        let selector = Selector::parse(selector).map_err(|e| format!("Invalid selector: {}", e))?;
+        let count = dict.add_phonetics_column(&selector)?;
+        info!(count, "entries with phonetics");
+    }
+    Ok(())
+}
+
+fn migrate(matches: &ArgMatches) -> Result<(), Box<dyn Error>> {
+    let source: &String = matches.get_one("input").expect("no source file");
+    ensure_raw_file(source)?;
+    let mut dict = RawDict::from(source);
+    dict.migrate_to_v2()?;
+    info!(source = %source, "migrated to schema v2");
+    Ok(())
+}
+
+async fn transform(matches: &ArgMatches) {
     let source: &String = matches.get_one("input").expect("no source file");
     let target: &String = matches.get_one("output").expect("no target file");
 
@@ -39,6 +877,46 @@ async fn main() {
         Some(v) => v.to_str().unwrap(),
         None => panic!("Invalid target file extension"),
     };
+    // only conversions of raw and StarDict dictionaries can select entries
+    let partial = matches!(
+        (source_ext, target_ext),
+        (EXT_RAW_ENTRY, EXT_ENTRY) | (EXT_RAW_RESOURCE, EXT_RESOURCE) | ("ifo", _)
+    );
+    let restricted = matches.get_one::<Regex>("filter").is_some()
+        || *matches.get_one::<u64>("offset").expect("no offset") > 0
+        || matches.get_one::<u64>("limit").is_some();
+    if !partial && restricted {
+        error!(
+            "--filter, --offset and --limit are only supported for `.{}` -> `.{}`, `.{}` -> `.{}` \
+            and `.ifo` conversions",
+            EXT_RAW_ENTRY, EXT_ENTRY, EXT_RAW_RESOURCE, EXT_RESOURCE
+        );
+        process::exit(1);
+    }
+    let raw_to_entry = (source_ext, target_ext) == (EXT_RAW_ENTRY, EXT_ENTRY);
+    // (flag, whether this conversion uses it)
+    let conversion_flags: &[(&str, bool)] = &[
+        ("entry-dedup-hash", raw_to_entry),
+        ("entry-format", raw_to_entry),
+        ("entry-schema", raw_to_entry),
+        (
+            "max-value-bytes",
+            (source_ext, target_ext) == (EXT_RAW_ENTRY, "csv"),
+        ),
+    ];
+    for &(id, used) in conversion_flags {
+        if !used && is_given(matches, id) {
+            error!(
+                "--{} is not supported for `.{}` -> `.{}`",
+                id, source_ext, target_ext
+            );
+            process::exit(1);
+        }
+    }
+    if let Err(e) = ensure_raw_options_used(matches, target_ext) {
+        error!("{}", e);
+        process::exit(1);
+    }
 
     match (source_ext, target_ext) {
         (EXT_ENTRY, EXT_RAW_ENTRY) | (EXT_RESOURCE, EXT_RAW_RESOURCE) => {
@@ -50,7 +928,7 @@ async fn main() {
             {
                 panic!("Invalid destination filename");
             }
-            let mut raw = RawDict::new(target);
+            let mut raw = RawDict::new_with_options(target, &raw_options(matches));
 
             let mut count = 0;
             dict.traverse_entry(&mut |key: &EntryKey, value: &EntryValue| {
@@ -71,8 +949,150 @@ async fn main() {
             raw.flush_token_cache();
         }
         (EXT_RAW_ENTRY, EXT_ENTRY) | (EXT_RAW_RESOURCE, EXT_RESOURCE) => {
+            let mut dict = RawDict::from(source);
+            dict.set_dedup_hash(matches.get_flag("entry-dedup-hash"));
+            dict.set_filter(filter_arg(matches));
+            dict.set_range(
+                *matches.get_one::<u64>("offset").expect("no offset"),
+                matches.get_one::<u64>("limit").copied(),
+            );
+            let json = matches
+                .get_one::<String>("entry-format")
+                .map(|v| v.as_str())
+                == Some("json");
+            let schema_path = matches.get_one::<String>("entry-schema");
+            if !json && schema_path.is_some() {
+                error!("--entry-schema requires --entry-format json");
+                process::exit(1);
+            }
+            if json {
+                let schema = match schema_path {
+                    Some(path) => match load_entry_schema(path) {
+                        Ok(v) => v,
+                        Err(e) => {
+                            error!("Invalid entry schema {}: {}", path, e);
+                            process::exit(1);
+                        }
+                    },
+                    None => EntrySchema::new(),
+                };
+                dict.set_entry_schema(Some(schema));
+            }
+            dict.to_beluga(target).await;
+        }
+        (EXT_ENTRY, "jsonl") | (EXT_RESOURCE, "jsonl") => {
+            let dict = Beluga::from_file(source).await;
+            let mut bar = ProgressBar::new(dict.metadata.entry_num);
+            let mut writer = JsonlWriter::new(
+                BufWriter::new(File::create(target).unwrap()),
+                dict.file_type,
+            );
+            let mut error = None;
+            dict.traverse_entry(&mut |key: &EntryKey, value: &EntryValue| {
+                if error.is_none() {
+                    error = writer.write_entry(key.0.as_str(), &value.0).err();
+                }
+                bar.inc();
+            });
+            bar.finish();
+            if let Some(e) = error {
+                panic!("{}", e);
+            }
+            let count = writer.finish().unwrap();
+            info!(count, "entries exported");
+        }
+        ("jsonl", EXT_ENTRY) | ("jsonl", EXT_RESOURCE) => {
+            let file_type = if target_ext == EXT_ENTRY {
+                BelFileType::Entry
+            } else {
+                BelFileType::Resource
+            };
+            let mut dict = read_jsonl(BufReader::new(File::open(source).unwrap()), file_type)
+                .unwrap_or_else(|e| panic!("Invalid JSON lines: {}", e));
+            dict.save(target);
+        }
+        (EXT_RAW_ENTRY, "csv") => {
             let dict = RawDict::from(source);
-            dict.to_beluga(&target).await;
+            let count = csv::raw_to_csv(
+                &dict,
+                BufWriter::new(File::create(target).unwrap()),
+                matches.get_one::<usize>("max-value-bytes").copied(),
+            )
+            .unwrap_or_else(|e| panic!("{}", e));
+            info!(count, "entries exported");
+        }
+        (EXT_RAW_RESOURCE, "csv") => {
+            panic!(
+                "Resource dictionaries can't be exported to CSV, use `.{}`",
+                EXT_RAW_ENTRY
+            )
+        }
+        (EXT_RESOURCE, "zip") | (EXT_RAW_RESOURCE, "zip") => {
+            let mut writer = ResourceZipWriter::new(File::create(target).unwrap());
+            let mut error = None;
+            if source_ext == EXT_RESOURCE {
+                let dict = Beluga::from_file(source).await;
+                let mut bar = ProgressBar::new(dict.metadata.entry_num);
+                dict.traverse_entry(&mut |key: &EntryKey, value: &EntryValue| {
+                    if error.is_none() {
+                        error = writer.add(key.0.as_str(), &value.0).err();
+                    }
+                    bar.inc();
+                });
+                bar.finish();
+                println!();
+            } else {
+                let dict = RawDict::from(source);
+                dict.traverse_entry(&mut |name: &str, value: &[u8]| {
+                    if error.is_none() {
+                        error = writer.add(name, value).err();
+                    }
+                })
+                .unwrap();
+            }
+            if let Some(e) = error {
+                panic!("{}", e);
+            }
+            let count = writer.finish().unwrap();
+            info!(count, "resources exported");
+        }
+        ("zip", EXT_RESOURCE) | ("zip", EXT_RAW_RESOURCE) => {
+            let file = File::open(source).unwrap();
+            let count = if target_ext == EXT_RESOURCE {
+                let mut dict = Beluga::new(Metadata::new(), BelFileType::Resource);
+                let count = read_resource_zip(BufReader::new(file), &mut |key, value| {
+                    dict.input_entry(key, value)
+                })
+                .unwrap_or_else(|e| panic!("Invalid ZIP archive: {}", e));
+                dict.save(target);
+                count
+            } else {
+                let mut raw = RawDict::new_with_options(target, &raw_options(matches));
+                let count = read_resource_zip(BufReader::new(file), &mut |key, value| {
+                    raw.insert_entry(&key, &value)
+                })
+                .unwrap_or_else(|e| panic!("Invalid ZIP archive: {}", e));
+                raw.flush_entry_cache();
+                count
+            };
+            info!(count, "resources imported");
+        }
+        ("ifo", EXT_ENTRY) | ("ifo", EXT_RAW_ENTRY) => {
+            let mut dict =
+                StarDict::new(source).unwrap_or_else(|e| panic!("Invalid StarDict: {}", e));
+            dict.set_filter(filter_arg(matches));
+            dict.set_range(
+                *matches.get_one::<u64>("offset").expect("no offset"),
+                matches.get_one::<u64>("limit").copied(),
+            );
+            let result = if target_ext == EXT_ENTRY {
+                dict.to_beluga(target).await
+            } else {
+                dict.to_raw(target, &raw_options(matches)).await
+            };
+            if let Err(e) = result {
+                panic!("{}", e);
+            }
         }
         _ => panic!("Invalid transform format"),
     }
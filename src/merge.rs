@@ -0,0 +1,135 @@
+use crate::raw::{RawDict, RawOptions};
+use beluga_core::beluga::*;
+use pbr::ProgressBar;
+use std::collections::HashMap;
+use std::error::Error;
+use std::path::Path;
+use tracing::info;
+
+/// Which definition is kept when several sources have the same entry.
+#[derive(Clone, Copy, PartialEq)]
+pub enum Conflict {
+    First,
+    Last,
+}
+
+/// (file type, is raw)
+fn dict_kind(path: &str) -> Result<(BelFileType, bool), Box<dyn Error>> {
+    match Path::new(path).extension().and_then(|v| v.to_str()) {
+        Some(EXT_ENTRY) => Ok((BelFileType::Entry, false)),
+        Some(EXT_RESOURCE) => Ok((BelFileType::Resource, false)),
+        Some(EXT_RAW_ENTRY) => Ok((BelFileType::Entry, true)),
+        Some(EXT_RAW_RESOURCE) => Ok((BelFileType::Resource, true)),
+        _ => Err(format!("Unsupported dictionary: {}", path).into()),
+    }
+}
+
+#[derive(Default)]
+struct Merged {
+    entries: Vec<(String, Vec<u8>)>,
+    entry_index: HashMap<String, usize>,
+    tokens: Vec<(String, Vec<String>)>,
+    token_index: HashMap<String, usize>,
+}
+
+impl Merged {
+    fn add_entry(&mut self, name: &str, value: &[u8], conflict: Conflict) {
+        match self.entry_index.get(name) {
+            Some(&i) => {
+                if conflict == Conflict::Last {
+                    self.entries[i].1 = value.to_vec();
+                }
+            }
+            None => {
+                self.entry_index
+                    .insert(name.to_string(), self.entries.len());
+                self.entries.push((name.to_string(), value.to_vec()));
+            }
+        }
+    }
+
+    /// Tokens of all sources are kept, their entries are united.
+    fn add_token(&mut self, name: &str, entries: Vec<String>) {
+        match self.token_index.get(name) {
+            Some(&i) => {
+                let list = &mut self.tokens[i].1;
+                for entry in entries {
+                    if !list.contains(&entry) {
+                        list.push(entry);
+                    }
+                }
+            }
+            None => {
+                self.token_index.insert(name.to_string(), self.tokens.len());
+                self.tokens.push((name.to_string(), entries));
+            }
+        }
+    }
+}
+
+/// Merge Beluga or raw dictionaries of the same file type into `dest`, which may also be either.
+/// Returns the number of entries of the merged dictionary.
+pub async fn merge(
+    sources: &[&str],
+    dest: &str,
+    conflict: Conflict,
+    options: &RawOptions,
+) -> Result<u64, Box<dyn Error>> {
+    let (file_type, dest_raw) = dict_kind(dest)?;
+    let mut merged = Merged::default();
+    for source in sources {
+        let (source_type, source_raw) = dict_kind(source)?;
+        if source_type != file_type {
+            return Err(format!("File type of {} differs from {}", source, dest).into());
+        }
+        info!(source, "merging");
+        if source_raw {
+            let dict = RawDict::from(source);
+            let mut bar = ProgressBar::new(dict.total_entries());
+            dict.traverse_entry(&mut |name: &str, value: &[u8]| {
+                merged.add_entry(name, value, conflict);
+                bar.inc();
+            })?;
+            bar.finish();
+            dict.traverse_token(&mut |name: &str, entries: Vec<String>| {
+                merged.add_token(name, entries);
+            })?;
+        } else {
+            let dict = Beluga::from_file(source).await;
+            let mut bar = ProgressBar::new(dict.metadata.entry_num);
+            dict.traverse_entry(&mut |key: &EntryKey, value: &EntryValue| {
+                merged.add_entry(key.0.as_str(), &value.0, conflict);
+                bar.inc();
+            });
+            bar.finish();
+            dict.traverse_token(&mut |key: &EntryKey, value: &EntryValue| {
+                merged.add_token(key.0.as_str(), Beluga::parse_token_entries(&value.0));
+            });
+        }
+    }
+
+    let total = merged.entries.len() as u64;
+    if dest_raw {
+        let mut raw = RawDict::new_with_options(dest, options);
+        for (name, value) in merged.entries {
+            raw.insert_entry(&name, &value);
+        }
+        raw.flush_entry_cache();
+        for (name, entries) in merged.tokens {
+            raw.insert_token_entries(&name, entries);
+        }
+        raw.flush_token_cache();
+    } else {
+        let mut meta = Metadata::new();
+        meta.entry_num = total;
+        let mut dict = Beluga::new(meta, file_type);
+        for (name, value) in merged.entries {
+            dict.input_entry(name, value);
+        }
+        for (name, entries) in merged.tokens {
+            dict.input_token(name, entries);
+        }
+        dict.save(dest);
+    }
+    Ok(total)
+}
@@ -1,10 +1,26 @@
+use crate::schema::EntrySchema;
+use crate::utils::fix_mojibake;
 use beluga_core::beluga::{BelFileType, Beluga, Metadata, EXT_RAW_ENTRY};
 use pbr::ProgressBar;
-use rusqlite::{params, Connection};
+use regex::Regex;
+use rusqlite::{params, Connection, Statement, ToSql};
+use scraper::{Html, Selector};
+use sha2::{Digest, Sha256};
+use std::collections::{HashMap, HashSet};
+use std::error::Error;
 use std::vec;
+use tokio::sync::watch;
+use tracing::{info, info_span, warn, Instrument};
+
+mod export;
+mod import;
 
 const ENTRY_TABLE: &str = "entry";
 const TOKEN_TABLE: &str = "token";
+const SCHEMA_INFO_TABLE: &str = "schema_info";
+const TOKEN_ENTRY_TABLE: &str = "token_entry";
+const ENTRY_FTS_TABLE: &str = "entry_fts";
+const SEARCH_LIMIT: usize = 50;
 
 #[derive(Debug)]
 struct Entry {
@@ -18,28 +34,167 @@ struct Token {
     entries: Vec<String>,
 }
 
+#[derive(Clone, Copy, PartialEq)]
+pub enum KeyCollation {
+    Binary,
+    NoCase,
+}
+
+pub struct RawOptions {
+    /// Collation of entry names, names equal under it are duplicates
+    pub key_collation: KeyCollation,
+    /// SQLite page cache size in KiB
+    pub cache_kb: u32,
+    /// WAL journal mode with `synchronous = NORMAL`
+    pub wal: bool,
+    /// FTS5 index of entry names and texts, kept in sync by triggers
+    pub fts: bool,
+    /// Number of cached entries or tokens written in one transaction
+    pub batch_size: usize,
+    /// `hash` column of SHA-256 fingerprints of entry values, see `RawDict::diff`
+    pub fingerprint: bool,
+}
+
+impl Default for RawOptions {
+    fn default() -> Self {
+        Self {
+            key_collation: KeyCollation::Binary,
+            cache_kb: 64 * 1024,
+            wal: true,
+            fts: false,
+            batch_size: 200,
+            fingerprint: false,
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+pub enum RepairStrategy {
+    /// Text that is UTF-8 decoded as Windows-1252
+    FixMojibake,
+    /// Remove invalid UTF-8 sequences
+    StripNonUtf8,
+}
+
 pub struct RawDict {
     file_type: BelFileType,
     conn: Connection,
     entry_cache: Vec<Entry>,
     token_cache: Vec<Token>,
     cache_size: usize,
+    dedup_hash: bool,
+    entry_schema: Option<EntrySchema>,
+    filter: Option<Regex>,
+    offset: u64,
+    limit: Option<u64>,
+    fingerprint: bool,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum DiffKind {
+    Added,
+    Removed,
+    Changed,
+}
+
+pub struct DiffEntry {
+    pub name: String,
+    pub kind: DiffKind,
+}
+
+fn sha256_hex(data: &[u8]) -> String {
+    Sha256::digest(data)
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+/// `INSERT` of an entry with its text or binary, parameters are `(name, value, hash)` when
+/// fingerprinted, otherwise `(name, value)`.
+fn entry_insert_sql(column: &str, fingerprint: bool) -> String {
+    if fingerprint {
+        format!(
+            "INSERT INTO {} (name, {}, hash) VALUES ($1, $2, $3)",
+            ENTRY_TABLE, column
+        )
+    } else {
+        format!(
+            "INSERT INTO {} (name, {}) VALUES ($1, $2)",
+            ENTRY_TABLE, column
+        )
+    }
+}
+
+/// `UPDATE` of one column of an entry by id, the fingerprint of its text is updated alongside.
+/// Parameters are `(value, hash, id)` when fingerprinted, otherwise `(value, id)`.
+fn entry_update_sql(column: &str, fingerprint: bool) -> String {
+    if fingerprint {
+        format!(
+            "UPDATE {} SET {} = $1, hash = $2 WHERE id = $3",
+            ENTRY_TABLE, column
+        )
+    } else {
+        format!("UPDATE {} SET {} = $1 WHERE id = $2", ENTRY_TABLE, column)
+    }
+}
+
+fn update_entry(
+    stmt: &mut Statement,
+    fingerprint: bool,
+    id: i64,
+    value: &dyn ToSql,
+    text: &str,
+) -> Result<usize, rusqlite::Error> {
+    if fingerprint {
+        stmt.execute(params![value, sha256_hex(text.as_bytes()), id])
+    } else {
+        stmt.execute(params![value, id])
+    }
 }
 
 impl RawDict {
-    pub fn new(filepath: &str) -> Self {
+    pub fn new_with_options(filepath: &str, options: &RawOptions) -> Self {
         let file_type = if filepath.ends_with(EXT_RAW_ENTRY) {
             BelFileType::Entry
         } else {
             BelFileType::Resource
         };
-        let conn = Connection::open(filepath).unwrap();
+        Self::create(Connection::open(filepath).unwrap(), file_type, options)
+    }
+
+    /// (Re)create the tables of a raw dictionary in `conn`.
+    fn create(conn: Connection, file_type: BelFileType, options: &RawOptions) -> Self {
+        let collation = match options.key_collation {
+            KeyCollation::Binary => "BINARY",
+            KeyCollation::NoCase => "NOCASE",
+        };
+        // negative cache size is in KiB
+        conn.pragma_update(None, "cache_size", -(options.cache_kb as i64))
+            .unwrap();
+        if options.wal {
+            conn.pragma_update_and_check(None, "journal_mode", "WAL", |row| {
+                row.get::<_, String>(0)
+            })
+            .unwrap();
+            conn.pragma_update(None, "synchronous", "NORMAL").unwrap();
+        }
+        // a recreated file is v1 again, `token_entry` rows of earlier versions may reference `token`
+        conn.execute_batch(
+            format!(
+                "DROP TABLE IF EXISTS {};
+                DROP TABLE IF EXISTS {};
+                ",
+                TOKEN_ENTRY_TABLE, SCHEMA_INFO_TABLE
+            )
+            .as_str(),
+        )
+        .unwrap();
         conn.execute_batch(
             format!(
                 "DROP TABLE IF EXISTS {};
                 CREATE TABLE {} (
                 id     INTEGER PRIMARY KEY AUTOINCREMENT,
-                name   TEXT UNIQUE,
+                name   TEXT UNIQUE COLLATE {},
                 text   TEXT,
                 binary BLOB
             );
@@ -47,7 +202,7 @@ impl RawDict {
                 name
             );
             ",
-                ENTRY_TABLE, ENTRY_TABLE, ENTRY_TABLE
+                ENTRY_TABLE, ENTRY_TABLE, collation, ENTRY_TABLE
             )
             .as_str(),
         )
@@ -70,12 +225,56 @@ impl RawDict {
             .as_str(),
         )
         .unwrap();
+        if options.fingerprint {
+            conn.execute(
+                format!("ALTER TABLE {} ADD COLUMN hash TEXT", ENTRY_TABLE).as_str(),
+                params![],
+            )
+            .unwrap();
+        }
+        conn.execute(
+            format!("DROP TABLE IF EXISTS {}", ENTRY_FTS_TABLE).as_str(),
+            params![],
+        )
+        .unwrap();
+        if options.fts && file_type == BelFileType::Entry {
+            conn.execute_batch(
+                format!(
+                    "CREATE VIRTUAL TABLE {fts} USING fts5(
+                        name, text, content='{entry}', content_rowid='id'
+                    );
+                    CREATE TRIGGER {fts}_insert AFTER INSERT ON {entry} BEGIN
+                        INSERT INTO {fts} (rowid, name, text) VALUES (new.id, new.name, new.text);
+                    END;
+                    CREATE TRIGGER {fts}_delete AFTER DELETE ON {entry} BEGIN
+                        INSERT INTO {fts} ({fts}, rowid, name, text)
+                            VALUES ('delete', old.id, old.name, old.text);
+                    END;
+                    CREATE TRIGGER {fts}_update AFTER UPDATE ON {entry} BEGIN
+                        INSERT INTO {fts} ({fts}, rowid, name, text)
+                            VALUES ('delete', old.id, old.name, old.text);
+                        INSERT INTO {fts} (rowid, name, text) VALUES (new.id, new.name, new.text);
+                    END;
+                    ",
+                    fts = ENTRY_FTS_TABLE,
+                    entry = ENTRY_TABLE
+                )
+                .as_str(),
+            )
+            .unwrap();
+        }
         Self {
             file_type,
             conn,
             entry_cache: vec![],
             token_cache: vec![],
-            cache_size: 200,
+            cache_size: options.batch_size.max(1),
+            dedup_hash: false,
+            entry_schema: None,
+            filter: None,
+            offset: 0,
+            limit: None,
+            fingerprint: options.fingerprint,
         }
     }
 
@@ -86,15 +285,52 @@ impl RawDict {
             BelFileType::Resource
         };
         let conn = Connection::open(filepath).unwrap();
+        let fingerprint = conn
+            .query_row(
+                "SELECT count(*) > 0 FROM pragma_table_info($1) WHERE name = 'hash'",
+                params![ENTRY_TABLE],
+                |row| row.get(0),
+            )
+            .unwrap_or(false);
         Self {
             file_type,
             conn,
             entry_cache: vec![],
             token_cache: vec![],
             cache_size: 200,
+            dedup_hash: false,
+            entry_schema: None,
+            filter: None,
+            offset: 0,
+            limit: None,
+            fingerprint,
         }
     }
 
+    /// Entries with the same definition as a previous entry are saved as `@@@LINK={previous_entry}`
+    /// by `to_beluga`. Only for entry dictionaries.
+    pub fn set_dedup_hash(&mut self, enable: bool) {
+        self.dedup_hash = enable;
+    }
+
+    /// Entries are saved as JSON by `to_beluga`, see `EntrySchema`. Only for entry dictionaries.
+    pub fn set_entry_schema(&mut self, schema: Option<EntrySchema>) {
+        self.entry_schema = schema;
+    }
+
+    /// Only entries whose names match are saved by `to_beluga`, tokens keep the saved entries.
+    pub fn set_filter(&mut self, filter: Option<Regex>) {
+        self.filter = filter;
+    }
+
+    /// Skip the first `offset` entries and save at most `limit` entries in `to_beluga`.
+    /// Counted after the filter, so both can be combined. Without a filter the range is part of
+    /// the query and skipped entries aren't read.
+    pub fn set_range(&mut self, offset: u64, limit: Option<u64>) {
+        self.offset = offset;
+        self.limit = limit;
+    }
+
     pub fn total_entries(&self) -> u64 {
         let mut stmt = self
             .conn
@@ -115,6 +351,386 @@ impl RawDict {
         row.get(0).unwrap()
     }
 
+    /// Visit all entries in id order, values are the text or the binary by the file type.
+    pub fn traverse_entry(&self, f: &mut impl FnMut(&str, &[u8])) -> Result<(), rusqlite::Error> {
+        let field = match self.file_type {
+            BelFileType::Entry => "CAST(text AS BLOB)",
+            BelFileType::Resource => "binary",
+        };
+        let mut stmt = self.conn.prepare(
+            format!(
+                "SELECT name, {} FROM {} ORDER BY id ASC",
+                field, ENTRY_TABLE
+            )
+            .as_str(),
+        )?;
+        let mut rows = stmt.query(params![])?;
+        while let Some(row) = rows.next()? {
+            let name: String = row.get(0)?;
+            let value: Option<Vec<u8>> = row.get(1)?;
+            f(&name, &value.unwrap_or_default());
+        }
+        Ok(())
+    }
+
+    /// Visit all tokens in id order with the names of their entries.
+    pub fn traverse_token(
+        &self,
+        f: &mut impl FnMut(&str, Vec<String>),
+    ) -> Result<(), rusqlite::Error> {
+        let mut stmt = self.conn.prepare(
+            format!("SELECT name, entries FROM {} ORDER BY id ASC", TOKEN_TABLE).as_str(),
+        )?;
+        let mut rows = stmt.query(params![])?;
+        while let Some(row) = rows.next()? {
+            let name: String = row.get(0)?;
+            let entries: Option<String> = row.get(1)?;
+            let entries: Vec<String> = entries
+                .and_then(|v| serde_json::from_str(&v).ok())
+                .unwrap_or_default();
+            f(&name, entries);
+        }
+        Ok(())
+    }
+
+    /// Names that appear more than once in the entry table.
+    /// `entry.name` is `UNIQUE` in files written by this tool, so only tables created or altered by
+    /// other tools can have duplicates.
+    pub fn compute_entry_duplicates(&self) -> Result<Vec<String>, rusqlite::Error> {
+        let mut stmt = self.conn.prepare(
+            format!(
+                "SELECT name, count(*) as c FROM {} GROUP BY name HAVING c > 1",
+                ENTRY_TABLE
+            )
+            .as_str(),
+        )?;
+        let rows = stmt.query_map(params![], |row| row.get(0))?;
+        rows.collect()
+    }
+
+    /// Entry names that are not referenced by any token, they can't be found by token search.
+    pub fn uncovered_entries(&self) -> Result<Vec<String>, rusqlite::Error> {
+        let mut stmt = self.conn.prepare(
+            format!(
+                "SELECT e.name FROM {} AS e
+                LEFT JOIN (SELECT DISTINCT j.value AS name FROM {} AS t, json_each(t.entries) AS j) AS c
+                ON e.name = c.name
+                WHERE c.name IS NULL
+                ORDER BY e.id ASC",
+                ENTRY_TABLE, TOKEN_TABLE
+            )
+            .as_str(),
+        )?;
+        let rows = stmt.query_map(params![], |row| row.get(0))?;
+        rows.collect()
+    }
+
+    /// Replace invalid UTF-8 sequences of text entries with U+FFFD, returns names of affected entries.
+    /// Such entries can only come from editing the database with other tools.
+    pub fn enforce_utf8(&mut self) -> Result<Vec<String>, rusqlite::Error> {
+        let fingerprint = self.fingerprint;
+        let tx = self.conn.transaction()?;
+        let mut names = vec![];
+        {
+            let mut stmt = tx.prepare(
+                format!(
+                    "SELECT id, name, CAST(text AS BLOB) FROM {} WHERE text IS NOT NULL",
+                    ENTRY_TABLE
+                )
+                .as_str(),
+            )?;
+            let mut update_stmt = tx.prepare(entry_update_sql("text", fingerprint).as_str())?;
+            let mut rows = stmt.query(params![])?;
+            while let Some(row) = rows.next()? {
+                let id: i64 = row.get(0)?;
+                let name: String = row.get(1)?;
+                let bytes: Vec<u8> = row.get(2)?;
+                if std::str::from_utf8(&bytes).is_err() {
+                    let text = String::from_utf8_lossy(&bytes);
+                    update_entry(&mut update_stmt, fingerprint, id, &text, &text)?;
+                    names.push(name);
+                }
+            }
+        }
+        tx.commit()?;
+        Ok(names)
+    }
+
+    /// Fix encoding problems of text entries, returns the number of repaired entries.
+    pub fn repair_entries(&mut self, strategy: RepairStrategy) -> Result<u64, rusqlite::Error> {
+        let fingerprint = self.fingerprint;
+        let tx = self.conn.transaction()?;
+        let mut count = 0;
+        {
+            let mut stmt = tx.prepare(
+                format!(
+                    "SELECT id, CAST(text AS BLOB) FROM {} WHERE text IS NOT NULL",
+                    ENTRY_TABLE
+                )
+                .as_str(),
+            )?;
+            let mut update_stmt = tx.prepare(entry_update_sql("text", fingerprint).as_str())?;
+            let mut rows = stmt.query(params![])?;
+            while let Some(row) = rows.next()? {
+                let id: i64 = row.get(0)?;
+                let bytes: Vec<u8> = row.get(1)?;
+                let repaired = match strategy {
+                    RepairStrategy::FixMojibake => match std::str::from_utf8(&bytes) {
+                        Ok(text) => fix_mojibake(text),
+                        Err(_) => None,
+                    },
+                    RepairStrategy::StripNonUtf8 => {
+                        if std::str::from_utf8(&bytes).is_ok() {
+                            None
+                        } else {
+                            Some(bytes.utf8_chunks().map(|v| v.valid()).collect::<String>())
+                        }
+                    }
+                };
+                if let Some(text) = repaired {
+                    update_entry(&mut update_stmt, fingerprint, id, &text, &text)?;
+                    count += 1;
+                }
+            }
+        }
+        tx.commit()?;
+        Ok(count)
+    }
+
+    /// Delete entries without both text and binary, returns the number of deleted entries.
+    pub fn repair_null_values(&mut self) -> Result<u64, rusqlite::Error> {
+        let count = self.conn.execute(
+            format!(
+                "DELETE FROM {} WHERE text IS NULL AND binary IS NULL",
+                ENTRY_TABLE
+            )
+            .as_str(),
+            params![],
+        )?;
+        Ok(count as u64)
+    }
+
+    fn has_column(&self, table: &str, column: &str) -> Result<bool, rusqlite::Error> {
+        self.conn.query_row(
+            "SELECT count(*) > 0 FROM pragma_table_info($1) WHERE name = $2",
+            params![table, column],
+            |row| row.get(0),
+        )
+    }
+
+    /// Add a `phonetics` column to the entry table and fill it with the text of the first element
+    /// matching `selector` in each definition. Returns the number of entries with phonetics.
+    /// Fingerprints are recomputed from the texts on the way.
+    pub fn add_phonetics_column(&mut self, selector: &Selector) -> Result<u64, rusqlite::Error> {
+        if !self.has_column(ENTRY_TABLE, "phonetics")? {
+            self.conn.execute(
+                format!("ALTER TABLE {} ADD COLUMN phonetics TEXT", ENTRY_TABLE).as_str(),
+                params![],
+            )?;
+        }
+        let fingerprint = self.fingerprint;
+        let tx = self.conn.transaction()?;
+        let mut count = 0;
+        {
+            let mut stmt = tx.prepare(
+                format!(
+                    "SELECT id, text FROM {} WHERE text IS NOT NULL",
+                    ENTRY_TABLE
+                )
+                .as_str(),
+            )?;
+            let mut update_stmt =
+                tx.prepare(entry_update_sql("phonetics", fingerprint).as_str())?;
+            let mut rows = stmt.query(params![])?;
+            while let Some(row) = rows.next()? {
+                let id: i64 = row.get(0)?;
+                let text: String = row.get(1)?;
+                let phonetics = Html::parse_fragment(&text)
+                    .select(selector)
+                    .next()
+                    .map(|v| v.text().collect::<String>().trim().to_string())
+                    .filter(|v| !v.is_empty());
+                if phonetics.is_some() {
+                    count += 1;
+                }
+                update_entry(&mut update_stmt, fingerprint, id, &phonetics, &text)?;
+            }
+        }
+        tx.commit()?;
+        Ok(count)
+    }
+
+    /// Entries whose names contain `query` (case insensitive for ASCII), exact matches and
+    /// shorter names first, at most 50. Uses the FTS5 index if the database has one, where
+    /// `query` matches a prefix of any word of the names.
+    pub fn search_entry(&self, query: &str) -> Result<Vec<(String, String)>, rusqlite::Error> {
+        let fts: bool = self.conn.query_row(
+            "SELECT count(*) > 0 FROM sqlite_master WHERE type = 'table' AND name = $1",
+            params![ENTRY_FTS_TABLE],
+            |row| row.get(0),
+        )?;
+        let (sql, pattern) = if fts {
+            (
+                format!(
+                    "SELECT e.name, coalesce(e.text, '') FROM {} f JOIN {} e ON e.id = f.rowid
+                    WHERE {} MATCH $1 ORDER BY e.name = $2 DESC, length(e.name), e.name LIMIT $3",
+                    ENTRY_FTS_TABLE, ENTRY_TABLE, ENTRY_FTS_TABLE
+                ),
+                format!("name : \"{}\" *", query.replace('"', "\"\"")),
+            )
+        } else {
+            (
+                format!(
+                    "SELECT name, coalesce(text, '') FROM {} WHERE name LIKE $1 ESCAPE '\\'
+                    ORDER BY name = $2 DESC, length(name), name LIMIT $3",
+                    ENTRY_TABLE
+                ),
+                format!(
+                    "%{}%",
+                    query
+                        .replace('\\', "\\\\")
+                        .replace('%', "\\%")
+                        .replace('_', "\\_")
+                ),
+            )
+        };
+        let mut stmt = self.conn.prepare(sql.as_str())?;
+        let rows = stmt.query_map(params![pattern, query, SEARCH_LIMIT], |row| {
+            Ok((row.get(0)?, row.get(1)?))
+        })?;
+        rows.collect()
+    }
+
+    /// Entries added, removed or changed in `other` compared to this dictionary, by name, which
+    /// ignores ASCII case if either uses NOCASE names. Both must be created with fingerprints.
+    pub fn diff(&self, other: &RawDict) -> Result<Vec<DiffEntry>, Box<dyn Error>> {
+        if !self.fingerprint || !other.fingerprint {
+            return Err("Both dictionaries must be created with --fingerprint".into());
+        }
+        // NOCASE only folds ASCII letters
+        let nocase = self.names_nocase()? || other.names_nocase()?;
+        let key = |name: &str| {
+            if nocase {
+                name.to_ascii_lowercase()
+            } else {
+                name.to_string()
+            }
+        };
+        let sql = format!("SELECT name, hash FROM {}", ENTRY_TABLE);
+        let mut hashes: HashMap<String, (String, Option<String>)> = HashMap::new();
+        {
+            let mut stmt = self.conn.prepare(sql.as_str())?;
+            let mut rows = stmt.query(params![])?;
+            while let Some(row) = rows.next()? {
+                let name: String = row.get(0)?;
+                hashes.insert(key(&name), (name, row.get(1)?));
+            }
+        }
+        let mut diff = vec![];
+        let mut stmt = other.conn.prepare(sql.as_str())?;
+        let mut rows = stmt.query(params![])?;
+        while let Some(row) = rows.next()? {
+            let name: String = row.get(0)?;
+            let hash: Option<String> = row.get(1)?;
+            let kind = match hashes.remove(&key(&name)) {
+                None => DiffKind::Added,
+                Some((_, v)) if v != hash => DiffKind::Changed,
+                Some(_) => continue,
+            };
+            diff.push(DiffEntry { name, kind });
+        }
+        diff.extend(hashes.into_values().map(|(name, _)| DiffEntry {
+            name,
+            kind: DiffKind::Removed,
+        }));
+        diff.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(diff)
+    }
+
+    /// Whether entry names are compared with `NOCASE`, see [`RawOptions::key_collation`].
+    fn names_nocase(&self) -> Result<bool, rusqlite::Error> {
+        self.conn.query_row(
+            "SELECT count(*) > 0 FROM sqlite_master \
+            WHERE type = 'table' AND name = $1 AND sql LIKE '%COLLATE NOCASE%'",
+            params![ENTRY_TABLE],
+            |row| row.get(0),
+        )
+    }
+
+    /// Schema version of the database, 1 when there is no `schema_info` table.
+    pub fn schema_version(&self) -> Result<u32, rusqlite::Error> {
+        let exists: bool = self.conn.query_row(
+            "SELECT count(*) > 0 FROM sqlite_master WHERE type = 'table' AND name = $1",
+            params![SCHEMA_INFO_TABLE],
+            |row| row.get(0),
+        )?;
+        if !exists {
+            return Ok(1);
+        }
+        let version: Option<u32> = self.conn.query_row(
+            format!("SELECT max(version) FROM {}", SCHEMA_INFO_TABLE).as_str(),
+            params![],
+            |row| row.get(0),
+        )?;
+        Ok(version.unwrap_or(1))
+    }
+
+    /// Add `schema_info` and copy JSON encoded token entries into the `token_entry` junction table.
+    /// The `token` table is kept as is and stays the source of `to_beluga`, `token_entry` is a
+    /// snapshot that later writes don't update. Does nothing if the database is already v2.
+    pub fn migrate_to_v2(&mut self) -> Result<(), rusqlite::Error> {
+        let tx = self.conn.transaction()?;
+        let exists: bool = tx.query_row(
+            "SELECT count(*) > 0 FROM sqlite_master WHERE type = 'table' AND name = $1",
+            params![SCHEMA_INFO_TABLE],
+            |row| row.get(0),
+        )?;
+        if exists {
+            let version: Option<u32> = tx.query_row(
+                format!("SELECT max(version) FROM {}", SCHEMA_INFO_TABLE).as_str(),
+                params![],
+                |row| row.get(0),
+            )?;
+            if version.unwrap_or(0) >= 2 {
+                return Ok(());
+            }
+        }
+        tx.execute_batch(
+            format!(
+                "CREATE TABLE IF NOT EXISTS {} (
+                    version INTEGER NOT NULL
+                );
+                DROP TABLE IF EXISTS {};
+                CREATE TABLE {} (
+                    token_id INTEGER NOT NULL,
+                    entry    TEXT    NOT NULL
+                );
+                CREATE INDEX token_entry_token ON {} (
+                    token_id
+                );
+                CREATE INDEX token_entry_entry ON {} (
+                    entry
+                );
+                INSERT INTO {} (token_id, entry)
+                    SELECT t.id, j.value FROM {} AS t, json_each(t.entries) AS j;
+                DELETE FROM {};
+                INSERT INTO {} (version) VALUES (2);
+                ",
+                SCHEMA_INFO_TABLE,
+                TOKEN_ENTRY_TABLE,
+                TOKEN_ENTRY_TABLE,
+                TOKEN_ENTRY_TABLE,
+                TOKEN_ENTRY_TABLE,
+                TOKEN_ENTRY_TABLE,
+                TOKEN_TABLE,
+                SCHEMA_INFO_TABLE,
+                SCHEMA_INFO_TABLE
+            )
+            .as_str(),
+        )?;
+        tx.commit()
+    }
+
     pub fn flush_entry_cache(&mut self) {
         let field = if self.file_type == BelFileType::Entry {
             "text"
@@ -122,21 +738,22 @@ impl RawDict {
             "binary"
         };
         let tx = self.conn.transaction().unwrap();
-        let sql = format!(
-            "INSERT INTO {} (name, {}) VALUES ($1, $2)",
-            ENTRY_TABLE, field
-        );
+        let sql = entry_insert_sql(field, self.fingerprint);
         {
             let mut stmt = tx.prepare(sql.as_str()).unwrap();
             for wd in &self.entry_cache {
-                if field == "text" {
-                    if let Err(e) = stmt.execute(params![wd.name, wd.text]) {
-                        eprintln!("fail to insert: {}\n {}", wd.name, e);
-                    }
+                let (value, bytes): (&dyn ToSql, Option<&[u8]>) = if field == "text" {
+                    (&wd.text, wd.text.as_ref().map(|v| v.as_bytes()))
                 } else {
-                    if let Err(e) = stmt.execute(params![wd.name, wd.binary]) {
-                        eprintln!("fail to insert: {}\n {}", wd.name, e);
-                    }
+                    (&wd.binary, wd.binary.as_deref())
+                };
+                let result = if self.fingerprint {
+                    stmt.execute(params![wd.name, value, bytes.map(sha256_hex)])
+                } else {
+                    stmt.execute(params![wd.name, value])
+                };
+                if let Err(e) = result {
+                    warn!(entry = %wd.name, error = %e, "fail to insert");
                 }
             }
         }
@@ -168,7 +785,7 @@ impl RawDict {
         if self.file_type == BelFileType::Entry {
             self.entry_cache.push(Entry {
                 name: String::from(name),
-                text: Some(String::from_utf8(value.to_vec()).unwrap()),
+                text: Some(String::from_utf8_lossy(value).into_owned()),
                 binary: None,
             });
         } else {
@@ -184,7 +801,10 @@ impl RawDict {
     }
 
     pub fn insert_token(&mut self, name: &str, value: &[u8]) {
-        let entries = Beluga::parse_token_entries(value);
+        self.insert_token_entries(name, Beluga::parse_token_entries(value));
+    }
+
+    pub fn insert_token_entries(&mut self, name: &str, entries: Vec<String>) {
         self.token_cache.push(Token {
             name: name.to_string(),
             entries,
@@ -194,25 +814,96 @@ impl RawDict {
         }
     }
 
+    /// Same as `to_beluga_with_progress`, progress is displayed with progress bars.
     pub async fn to_beluga(&self, dest: &str) {
+        let entry_num = self.total_entries();
+        let token_num = self.total_tokens();
+        let (tx, mut rx) = watch::channel(0);
+        let bars = tokio::spawn(async move {
+            info!(entries = entry_num, "transformating entry table");
+            let mut entry_bar = ProgressBar::new(entry_num);
+            let mut token_bar = None;
+            loop {
+                let closed = rx.changed().await.is_err();
+                let count = *rx.borrow_and_update();
+                if count > entry_num && token_bar.is_none() {
+                    entry_bar.set(entry_num);
+                    entry_bar.finish();
+                    println!();
+                    info!(tokens = token_num, "transformating token table");
+                    token_bar = Some(ProgressBar::new(token_num));
+                }
+                match token_bar.as_mut() {
+                    Some(bar) => bar.set(count - entry_num),
+                    None => entry_bar.set(count),
+                };
+                if closed {
+                    break;
+                }
+            }
+            // the token bar is also shown for an empty token table
+            let mut token_bar = token_bar.unwrap_or_else(|| {
+                entry_bar.finish();
+                println!();
+                info!(tokens = token_num, "transformating token table");
+                ProgressBar::new(token_num)
+            });
+            token_bar.finish();
+            println!();
+        });
+        self.to_beluga_with_progress(dest, tx)
+            .instrument(info_span!(
+                "to_beluga",
+                dest,
+                entries = entry_num,
+                tokens = token_num
+            ))
+            .await;
+        bars.await.unwrap();
+    }
+
+    /// `progress` receives the number of entries and tokens transformed so far,
+    /// entries come first and then tokens.
+    pub async fn to_beluga_with_progress(&self, dest: &str, progress: watch::Sender<u64>) {
         let meta = Metadata::new();
         let mut dict = Beluga::new(meta, self.file_type);
         let mut id = 0;
         let limit = 100;
-        println!("Transformating entry table...");
-        let mut bar = ProgressBar::new(self.total_entries());
-        loop {
+        let mut done: u64 = 0;
+        let dedup = self.dedup_hash && self.file_type == BelFileType::Entry;
+        let mut hashes: HashMap<blake3::Hash, String> = HashMap::new();
+        let entry_num = self.total_entries();
+        let restricted = self.filter.is_some() || self.offset > 0 || self.limit.is_some();
+        // names of saved entries, tokens may only refer to them
+        let mut included: HashSet<String> = HashSet::new();
+        let mut matched: u64 = 0;
+        let mut taken: u64 = 0;
+        // without a filter the range is applied by the query, otherwise it's counted after the
+        // filter and all entries have to be read
+        let (mut skip, offset) = match self.filter {
+            Some(_) => (0, self.offset),
+            None => (self.offset, 0),
+        };
+        'entries: loop {
+            let page = match (&self.filter, self.limit) {
+                (None, Some(v)) => (limit as u64).min(v - taken),
+                _ => limit as u64,
+            };
+            if page == 0 {
+                break;
+            }
             let mut stmt = self
                 .conn
                 .prepare(
                     format!(
-                        "SELECT * FROM {} WHERE id > $1 ORDER BY id ASC LIMIT $2",
+                        "SELECT * FROM {} WHERE id > $1 ORDER BY id ASC LIMIT $2 OFFSET $3",
                         ENTRY_TABLE
                     )
                     .as_str(),
                 )
                 .unwrap();
-            let mut list = stmt.query(params![id, limit]).unwrap();
+            let mut list = stmt.query(params![id, page, skip]).unwrap();
+            skip = 0;
             let mut rows: Vec<Entry> = Vec::new();
             while let Ok(Some(row)) = list.next() {
                 id = row.get(0).unwrap();
@@ -224,56 +915,216 @@ impl RawDict {
             }
             let count = rows.len();
             for word in rows {
-                let value = match self.file_type {
+                if self.limit.is_some_and(|v| taken >= v) {
+                    break 'entries;
+                }
+                done += 1;
+                if self
+                    .filter
+                    .as_ref()
+                    .is_some_and(|v| !v.is_match(&word.name))
+                {
+                    continue;
+                }
+                matched += 1;
+                if matched <= offset {
+                    continue;
+                }
+                taken += 1;
+                if restricted {
+                    included.insert(word.name.clone());
+                }
+                let mut value = match self.file_type {
                     BelFileType::Entry => word.text.unwrap().as_bytes().to_vec(),
                     BelFileType::Resource => word.binary.unwrap(),
                 };
+                if dedup && !value.starts_with(b"@@@LINK=") {
+                    let hash = blake3::hash(&value);
+                    match hashes.get(&hash) {
+                        Some(first) if first.len() + 8 < value.len() => {
+                            value = format!("@@@LINK={}", first).into_bytes();
+                        }
+                        Some(_) => {}
+                        None => {
+                            hashes.insert(hash, word.name.clone());
+                        }
+                    }
+                }
+                if let (BelFileType::Entry, Some(schema)) = (self.file_type, &self.entry_schema) {
+                    if !value.starts_with(b"@@@LINK=") {
+                        value = schema
+                            .to_json(&String::from_utf8_lossy(&value))
+                            .into_bytes();
+                    }
+                }
                 dict.input_entry(word.name, value);
-                bar.inc();
             }
-            if count < limit {
+            progress.send_replace(done);
+            if (count as u64) < page {
                 break;
             }
         }
-        bar.finish();
-        let token_num = self.total_tokens();
-        if token_num > 0 {
-            id = 0;
-            println!("Transformating token table...");
-            bar = ProgressBar::new(token_num);
-            loop {
-                let mut stmt = self
-                    .conn
-                    .prepare(
-                        format!(
-                            "SELECT * FROM {} WHERE id > $1 ORDER BY id ASC LIMIT $2",
-                            TOKEN_TABLE
-                        )
-                        .as_str(),
+        done = entry_num;
+        progress.send_replace(done);
+        id = 0;
+        loop {
+            let mut stmt = self
+                .conn
+                .prepare(
+                    format!(
+                        "SELECT * FROM {} WHERE id > $1 ORDER BY id ASC LIMIT $2",
+                        TOKEN_TABLE
                     )
-                    .unwrap();
-                let mut list = stmt.query(params![id, limit]).unwrap();
-                let mut rows: Vec<Token> = Vec::new();
-                while let Ok(Some(row)) = list.next() {
-                    id = row.get(0).unwrap();
-                    let json: String = row.get(2).unwrap();
-                    let entries: Vec<String> = serde_json::from_slice(json.as_bytes()).unwrap();
-                    rows.push(Token {
-                        name: row.get(1).unwrap(),
-                        entries,
-                    })
-                }
-                let count = rows.len();
-                for row in rows {
-                    dict.input_token(row.name, row.entries);
-                    bar.inc();
-                }
-                if count < limit {
-                    break;
+                    .as_str(),
+                )
+                .unwrap();
+            let mut list = stmt.query(params![id, limit]).unwrap();
+            let mut rows: Vec<Token> = Vec::new();
+            while let Ok(Some(row)) = list.next() {
+                id = row.get(0).unwrap();
+                let json: String = row.get(2).unwrap();
+                let entries: Vec<String> = serde_json::from_slice(json.as_bytes()).unwrap();
+                rows.push(Token {
+                    name: row.get(1).unwrap(),
+                    entries,
+                })
+            }
+            let count = rows.len();
+            for mut row in rows {
+                done += 1;
+                if restricted {
+                    row.entries.retain(|v| included.contains(v));
+                    if row.entries.is_empty() {
+                        continue;
+                    }
                 }
+                dict.input_token(row.name, row.entries);
+            }
+            progress.send_replace(done);
+            if count < limit {
+                break;
             }
-            bar.finish();
         }
         dict.save(dest);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn dict(fts: bool) -> RawDict {
+        let options = RawOptions {
+            wal: false,
+            fts,
+            ..RawOptions::default()
+        };
+        let mut dict = RawDict::create(
+            Connection::open_in_memory().unwrap(),
+            BelFileType::Entry,
+            &options,
+        );
+        for name in [
+            "pineapple",
+            "apple pie",
+            "apple",
+            "grape",
+            "50%",
+            "a_b",
+            "axb",
+            "say \"hi\"",
+        ] {
+            dict.insert_entry(name, format!("<p>{}</p>", name).as_bytes());
+        }
+        dict.flush_entry_cache();
+        dict
+    }
+
+    fn names(dict: &RawDict, query: &str) -> Vec<String> {
+        dict.search_entry(query)
+            .unwrap()
+            .into_iter()
+            .map(|(name, _)| name)
+            .collect()
+    }
+
+    #[test]
+    fn search_like() {
+        let dict = dict(false);
+        assert_eq!(names(&dict, "apple"), ["apple", "apple pie", "pineapple"]);
+        assert_eq!(names(&dict, "APP"), ["apple", "apple pie", "pineapple"]);
+        assert_eq!(
+            dict.search_entry("grape").unwrap(),
+            [(String::from("grape"), String::from("<p>grape</p>"))]
+        );
+        assert!(names(&dict, "banana").is_empty());
+    }
+
+    #[test]
+    fn search_like_escapes() {
+        let dict = dict(false);
+        assert_eq!(names(&dict, "%"), ["50%"]);
+        assert_eq!(names(&dict, "_"), ["a_b"]);
+        assert_eq!(names(&dict, "\"hi"), ["say \"hi\""]);
+    }
+
+    #[test]
+    fn search_fts() {
+        let dict = dict(true);
+        assert_eq!(names(&dict, "apple"), ["apple", "apple pie"]);
+        assert_eq!(names(&dict, "pie"), ["apple pie"]);
+        assert_eq!(names(&dict, "pine"), ["pineapple"]);
+        assert!(names(&dict, "banana").is_empty());
+    }
+
+    #[test]
+    fn search_fts_escapes() {
+        let dict = dict(true);
+        assert_eq!(names(&dict, "say \"hi\""), ["say \"hi\""]);
+        assert_eq!(names(&dict, "\"hi"), ["say \"hi\""]);
+        assert_eq!(names(&dict, "50%"), ["50%"]);
+        assert_eq!(names(&dict, "a_b"), ["a_b"]);
+    }
+
+    fn fingerprinted(key_collation: KeyCollation, entries: &[(&str, &str)]) -> RawDict {
+        let options = RawOptions {
+            key_collation,
+            wal: false,
+            fingerprint: true,
+            ..RawOptions::default()
+        };
+        let mut dict = RawDict::create(
+            Connection::open_in_memory().unwrap(),
+            BelFileType::Entry,
+            &options,
+        );
+        for (name, text) in entries {
+            dict.insert_entry(name, text.as_bytes());
+        }
+        dict.flush_entry_cache();
+        dict
+    }
+
+    #[test]
+    fn nocase_names() {
+        let entries = [("Apple", "<p>1</p>"), ("apple", "<p>2</p>"), ("pear", "")];
+        assert_eq!(
+            fingerprinted(KeyCollation::Binary, &entries).total_entries(),
+            3
+        );
+        assert_eq!(
+            fingerprinted(KeyCollation::NoCase, &entries).total_entries(),
+            2
+        );
+
+        let old = fingerprinted(KeyCollation::NoCase, &[("Apple", "<p>1</p>"), ("Pear", "")]);
+        let new = fingerprinted(
+            KeyCollation::Binary,
+            &[("apple", "<p>1</p>"), ("pear", "1")],
+        );
+        let diff = old.diff(&new).unwrap();
+        assert_eq!(diff.len(), 1);
+        assert_eq!(diff[0].name, "pear");
+        assert!(matches!(diff[0].kind, DiffKind::Changed));
+    }
+}
@@ -0,0 +1,695 @@
+use super::{RawDict, RawOptions, ENTRY_TABLE};
+use crate::csv::CsvWriter;
+use crate::schema::CsvSchema;
+use crate::utils::{escape_html, percent_encode, rfc3339_now, strip_html, VOID_ELEMENTS};
+use beluga_core::beluga::{BelFileType, EXT_RAW_ENTRY, EXT_RAW_RESOURCE};
+use rusqlite::types::ValueRef;
+use rusqlite::{params, params_from_iter, Connection};
+use scraper::{ElementRef, Html, Node};
+use std::collections::{BTreeMap, HashMap};
+use std::error::Error;
+use std::fs;
+use std::io::Write;
+use std::path::Path;
+use zip::write::SimpleFileOptions;
+use zip::{CompressionMethod, ZipWriter};
+
+fn generator() -> String {
+    format!("beluga-builder {}", env!("CARGO_PKG_VERSION"))
+}
+
+fn write_xhtml(element: ElementRef, out: &mut String) {
+    for child in element.children() {
+        match child.value() {
+            Node::Text(text) => out.push_str(&escape_html(text)),
+            Node::Element(e) => {
+                out.push('<');
+                out.push_str(e.name());
+                for (name, value) in e.attrs() {
+                    out.push_str(&format!(" {}=\"{}\"", name, escape_html(value)));
+                }
+                if VOID_ELEMENTS.contains(&e.name()) {
+                    out.push_str("/>");
+                    continue;
+                }
+                out.push('>');
+                if let Some(v) = ElementRef::wrap(child) {
+                    write_xhtml(v, out);
+                }
+                out.push_str(&format!("</{}>", e.name()));
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Re-serialize an HTML fragment as well-formed XHTML.
+fn to_xhtml(html: &str) -> String {
+    let fragment = Html::parse_fragment(html);
+    let mut out = String::with_capacity(html.len());
+    write_xhtml(fragment.root_element(), &mut out);
+    out
+}
+
+impl RawDict {
+    fn ensure_entry_type(&self, format: &str) -> Result<(), Box<dyn Error>> {
+        if self.file_type != BelFileType::Entry {
+            return Err(format!("{} export only supports entry dictionaries", format).into());
+        }
+        Ok(())
+    }
+
+    /// Export entry names only into a FTS5 table, for services that only need searching.
+    pub fn export_to_sqlite_fts(&self, dest: &str) -> Result<(), Box<dyn Error>> {
+        let mut conn = Connection::open(dest)?;
+        conn.execute_batch(
+            "DROP TABLE IF EXISTS entry_fts;
+            CREATE VIRTUAL TABLE entry_fts USING fts5(name);
+            ",
+        )?;
+        let tx = conn.transaction()?;
+        {
+            let mut insert_stmt = tx.prepare("INSERT INTO entry_fts (name) VALUES ($1)")?;
+            let mut stmt = self
+                .conn
+                .prepare(format!("SELECT name FROM {} ORDER BY id ASC", ENTRY_TABLE).as_str())?;
+            let mut rows = stmt.query(params![])?;
+            while let Some(row) = rows.next()? {
+                let name: String = row.get(0)?;
+                insert_stmt.execute(params![name])?;
+            }
+        }
+        tx.commit()?;
+        conn.execute_batch("INSERT INTO entry_fts (entry_fts) VALUES ('optimize'); VACUUM;")?;
+        Ok(())
+    }
+
+    /// Write unique trimmed entry names, one per line and sorted case insensitively, for
+    /// vocabulary learning apps.
+    pub fn export_vocabulary_list(
+        &self,
+        mut writer: impl Write,
+        exclude_multiword: bool,
+    ) -> Result<usize, Box<dyn Error>> {
+        let mut stmt = self
+            .conn
+            .prepare(format!("SELECT name FROM {}", ENTRY_TABLE).as_str())?;
+        let mut rows = stmt.query(params![])?;
+        let mut names: Vec<(String, String)> = vec![];
+        while let Some(row) = rows.next()? {
+            let name: String = row.get(0)?;
+            let name = name.trim();
+            if name.is_empty() || (exclude_multiword && name.contains(char::is_whitespace)) {
+                continue;
+            }
+            names.push((name.to_lowercase(), name.to_string()));
+        }
+        names.sort();
+        names.dedup_by(|a, b| a.1 == b.1);
+        for (_, name) in &names {
+            writeln!(writer, "{}", name)?;
+        }
+        writer.flush()?;
+        Ok(names.len())
+    }
+
+    /// Write `{name}\t{plain text}` lines, definitions are stripped of HTML.
+    pub fn export_definitions_plaintext(
+        &self,
+        mut writer: impl Write,
+    ) -> Result<usize, Box<dyn Error>> {
+        self.ensure_entry_type("Plain text")?;
+        let mut stmt = self
+            .conn
+            .prepare(format!("SELECT name, text FROM {} ORDER BY id ASC", ENTRY_TABLE).as_str())?;
+        let mut rows = stmt.query(params![])?;
+        let mut count = 0;
+        while let Some(row) = rows.next()? {
+            let name: String = row.get(0)?;
+            let text: Option<String> = row.get(1)?;
+            let text = strip_html(text.as_deref().unwrap_or_default());
+            writeln!(writer, "{}\t{}", name, text)?;
+            count += 1;
+        }
+        writer.flush()?;
+        Ok(count)
+    }
+
+    /// Write CC-CEDICT lines: `traditional simplified [pinyin] /definition1/definition2/`.
+    /// The entry name is the simplified form, traditional and pinyin are read from extra columns
+    /// of the entry table. Without `traditional_col` the simplified form is used for both.
+    pub fn export_to_cedict(
+        &self,
+        mut writer: impl Write,
+        traditional_col: Option<&str>,
+        pinyin_col: &str,
+    ) -> Result<usize, Box<dyn Error>> {
+        self.ensure_entry_type("CC-CEDICT")?;
+        for col in traditional_col.iter().chain([pinyin_col].iter()) {
+            if col.is_empty() || !col.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+                return Err(format!("Invalid column name: {}", col).into());
+            }
+            // unknown double quoted identifiers are string literals in SQLite
+            if !self.has_column(ENTRY_TABLE, col)? {
+                return Err(format!("Column not found: {}", col).into());
+            }
+        }
+        let sql = format!(
+            "SELECT name, text, \"{}\", \"{}\" FROM {} ORDER BY id ASC",
+            traditional_col.unwrap_or("name"),
+            pinyin_col,
+            ENTRY_TABLE
+        );
+        let mut stmt = self.conn.prepare(sql.as_str())?;
+        let mut rows = stmt.query(params![])?;
+        let mut count = 0;
+        while let Some(row) = rows.next()? {
+            let simplified: String = row.get(0)?;
+            let text: Option<String> = row.get(1)?;
+            let traditional: Option<String> = row.get(2)?;
+            let pinyin: Option<String> = row.get(3)?;
+            let mut html = text.unwrap_or_default();
+            for tag in ["<br>", "<br/>", "<br />", "</li>", "</p>", "</div>"] {
+                html = html.replace(tag, "\n");
+            }
+            let definitions: Vec<String> = html
+                .lines()
+                .map(|v| strip_html(v).replace('/', ";"))
+                .filter(|v| !v.is_empty())
+                .collect();
+            if definitions.is_empty() {
+                continue;
+            }
+            writeln!(
+                writer,
+                "{} {} [{}] /{}/",
+                traditional.unwrap_or_else(|| simplified.clone()),
+                simplified,
+                pinyin.unwrap_or_default().trim(),
+                definitions.join("/")
+            )?;
+            count += 1;
+        }
+        writer.flush()?;
+        Ok(count)
+    }
+
+    /// Write an OPDS (Atom) navigation catalog, every entry links to `{base_url}/entry/{name}`.
+    pub fn export_to_opds(
+        &self,
+        base_url: &str,
+        title: &str,
+        mut writer: impl Write,
+    ) -> Result<(), Box<dyn Error>> {
+        self.ensure_entry_type("OPDS")?;
+        let base_url = base_url.trim_end_matches('/');
+        let updated = rfc3339_now();
+        writeln!(writer, r#"<?xml version="1.0" encoding="UTF-8"?>"#)?;
+        writeln!(
+            writer,
+            r#"<feed xmlns="http://www.w3.org/2005/Atom" xmlns:opds="http://opds-spec.org/2010/catalog">"#
+        )?;
+        writeln!(writer, "  <id>{}</id>", escape_html(base_url))?;
+        writeln!(writer, "  <title>{}</title>", escape_html(title))?;
+        writeln!(writer, "  <updated>{}</updated>", updated)?;
+        writeln!(
+            writer,
+            r#"  <link rel="self" href="{}" type="application/atom+xml;profile=opds-catalog;kind=navigation"/>"#,
+            escape_html(base_url)
+        )?;
+        let mut stmt = self
+            .conn
+            .prepare(format!("SELECT name, text FROM {} ORDER BY id ASC", ENTRY_TABLE).as_str())?;
+        let mut rows = stmt.query(params![])?;
+        while let Some(row) = rows.next()? {
+            let name: String = row.get(0)?;
+            let text: Option<String> = row.get(1)?;
+            let href = escape_html(&format!("{}/entry/{}", base_url, percent_encode(&name)));
+            writeln!(writer, "  <entry>")?;
+            writeln!(writer, "    <title>{}</title>", escape_html(&name))?;
+            writeln!(writer, "    <id>{}</id>", href)?;
+            writeln!(writer, "    <updated>{}</updated>", updated)?;
+            writeln!(
+                writer,
+                r#"    <link rel="alternate" href="{}" type="text/html"/>"#,
+                href
+            )?;
+            writeln!(
+                writer,
+                "    <summary>{}</summary>",
+                escape_html(&strip_html(text.as_deref().unwrap_or_default()))
+            )?;
+            writeln!(writer, "  </entry>")?;
+        }
+        writeln!(writer, "</feed>")?;
+        writer.flush()?;
+        Ok(())
+    }
+
+    /// Write a MediaWiki XML dump with one page per entry, for `importDump.php`.
+    pub fn export_to_wikisaurus(&self, mut writer: impl Write) -> Result<usize, Box<dyn Error>> {
+        self.ensure_entry_type("MediaWiki")?;
+        let timestamp = rfc3339_now();
+        writeln!(
+            writer,
+            r#"<mediawiki xmlns="http://www.mediawiki.org/xml/export-0.10/" version="0.10">"#
+        )?;
+        let mut stmt = self
+            .conn
+            .prepare(format!("SELECT name, text FROM {} ORDER BY id ASC", ENTRY_TABLE).as_str())?;
+        let mut rows = stmt.query(params![])?;
+        let mut count = 0;
+        while let Some(row) = rows.next()? {
+            let name: String = row.get(0)?;
+            let text: Option<String> = row.get(1)?;
+            let wikitext = format!(
+                "== {} ==\n=== Definition ===\n{}\n",
+                name,
+                strip_html(text.as_deref().unwrap_or_default())
+            );
+            writeln!(writer, "  <page>")?;
+            writeln!(writer, "    <title>{}</title>", escape_html(&name))?;
+            writeln!(writer, "    <ns>0</ns>")?;
+            writeln!(writer, "    <revision>")?;
+            writeln!(writer, "      <timestamp>{}</timestamp>", timestamp)?;
+            writeln!(
+                writer,
+                "      <contributor><username>beluga-builder</username></contributor>"
+            )?;
+            writeln!(writer, "      <model>wikitext</model>")?;
+            writeln!(writer, "      <format>text/x-wiki</format>")?;
+            writeln!(
+                writer,
+                r#"      <text xml:space="preserve">{}</text>"#,
+                escape_html(&wikitext)
+            )?;
+            writeln!(writer, "    </revision>")?;
+            writeln!(writer, "  </page>")?;
+            count += 1;
+        }
+        writeln!(writer, "</mediawiki>")?;
+        writer.flush()?;
+        Ok(count)
+    }
+
+    /// Split entries into `{output_dir}/{initial}.bel-db` (or `.beld-db`) by the lowercased first
+    /// character of their names, returns the number of entries of each file. Non alphanumeric
+    /// initials are written as `u{code point}`. Tokens are not exported.
+    pub fn export_entries_by_initial_letter(
+        &self,
+        output_dir: &str,
+    ) -> Result<HashMap<char, u64>, Box<dyn Error>> {
+        fs::create_dir_all(output_dir)?;
+        let ext = match self.file_type {
+            BelFileType::Entry => EXT_RAW_ENTRY,
+            BelFileType::Resource => EXT_RAW_RESOURCE,
+        };
+        let mut groups: HashMap<char, Vec<String>> = HashMap::new();
+        {
+            let mut stmt = self.conn.prepare(
+                format!("SELECT DISTINCT substr(name, 1, 1) FROM {}", ENTRY_TABLE).as_str(),
+            )?;
+            let mut rows = stmt.query(params![])?;
+            while let Some(row) = rows.next()? {
+                let initial: Option<String> = row.get(0)?;
+                let c = match initial.as_deref().and_then(|v| v.chars().next()) {
+                    Some(v) => v,
+                    None => continue,
+                };
+                let key = c.to_lowercase().next().unwrap_or(c);
+                groups.entry(key).or_default().push(c.to_string());
+            }
+        }
+        let mut counts: HashMap<char, u64> = HashMap::new();
+        for (key, initials) in groups {
+            let filename = if key.is_alphanumeric() {
+                format!("{}.{}", key, ext)
+            } else {
+                format!("u{:04x}.{}", key as u32, ext)
+            };
+            let path = Path::new(output_dir).join(filename);
+            let mut shard = RawDict::new_with_options(
+                path.to_str().ok_or("Invalid output directory")?,
+                &RawOptions::default(),
+            );
+            let placeholders = vec!["?"; initials.len()].join(", ");
+            let mut stmt = self.conn.prepare(
+                format!(
+                    "SELECT name, text, binary FROM {} WHERE substr(name, 1, 1) IN ({}) ORDER BY id ASC",
+                    ENTRY_TABLE, placeholders
+                )
+                .as_str(),
+            )?;
+            let mut rows = stmt.query(params_from_iter(initials.iter()))?;
+            let mut count = 0;
+            while let Some(row) = rows.next()? {
+                let name: String = row.get(0)?;
+                let value = match self.file_type {
+                    BelFileType::Entry => row.get::<_, Option<String>>(1)?.map(|v| v.into_bytes()),
+                    BelFileType::Resource => row.get::<_, Option<Vec<u8>>>(2)?,
+                };
+                shard.insert_entry(&name, &value.unwrap_or_default());
+                count += 1;
+            }
+            shard.flush_entry_cache();
+            counts.insert(key, count);
+        }
+        Ok(counts)
+    }
+
+    /// Write a LinguaLeo word list: `[{"word": "...", "translation": "...", "transcription": "..."}]`.
+    /// Translations are stripped definitions, transcriptions come from the `phonetics` column.
+    pub fn export_to_lingualeo(&self, mut writer: impl Write) -> Result<usize, Box<dyn Error>> {
+        self.ensure_entry_type("LinguaLeo")?;
+        let phonetics = if self.has_column(ENTRY_TABLE, "phonetics")? {
+            "phonetics"
+        } else {
+            "NULL"
+        };
+        let mut stmt = self.conn.prepare(
+            format!(
+                "SELECT name, text, {} FROM {} ORDER BY id ASC",
+                phonetics, ENTRY_TABLE
+            )
+            .as_str(),
+        )?;
+        let mut rows = stmt.query(params![])?;
+        let mut count = 0;
+        write!(writer, "[")?;
+        while let Some(row) = rows.next()? {
+            let name: String = row.get(0)?;
+            let text: Option<String> = row.get(1)?;
+            let transcription: Option<String> = row.get(2)?;
+            if count > 0 {
+                write!(writer, ",")?;
+            }
+            let word = serde_json::json!({
+                "word": name,
+                "translation": strip_html(text.as_deref().unwrap_or_default()),
+                "transcription": transcription.unwrap_or_default(),
+            });
+            write!(writer, "\n  ")?;
+            serde_json::to_writer(&mut writer, &word)?;
+            count += 1;
+        }
+        writeln!(writer, "\n]")?;
+        writer.flush()?;
+        Ok(count)
+    }
+
+    /// Write XDXF, definitions are kept as HTML in CDATA sections.
+    pub fn export_to_xdxf(
+        &self,
+        mut writer: impl Write,
+        title: &str,
+        src_lang: &str,
+        tgt_lang: &str,
+    ) -> Result<usize, Box<dyn Error>> {
+        self.ensure_entry_type("XDXF")?;
+        let now = rfc3339_now();
+        // DD-MM-YYYY
+        let date = format!("{}-{}-{}", &now[8..10], &now[5..7], &now[0..4]);
+        writeln!(writer, r#"<?xml version="1.0" encoding="UTF-8"?>"#)?;
+        writeln!(
+            writer,
+            r#"<xdxf lang_from="{}" lang_to="{}" format="visual" revision="033">"#,
+            escape_html(&src_lang.to_uppercase()),
+            escape_html(&tgt_lang.to_uppercase())
+        )?;
+        writeln!(writer, "  <meta_info>")?;
+        writeln!(writer, "    <title>{}</title>", escape_html(title))?;
+        writeln!(
+            writer,
+            "    <full_title>{}</full_title>",
+            escape_html(title)
+        )?;
+        writeln!(writer, "    <file_ver>001</file_ver>")?;
+        writeln!(writer, "    <creation_date>{}</creation_date>", date)?;
+        writeln!(writer, "  </meta_info>")?;
+        writeln!(writer, "  <lexicon>")?;
+        let mut stmt = self
+            .conn
+            .prepare(format!("SELECT name, text FROM {} ORDER BY id ASC", ENTRY_TABLE).as_str())?;
+        let mut rows = stmt.query(params![])?;
+        let mut count = 0;
+        while let Some(row) = rows.next()? {
+            let name: String = row.get(0)?;
+            let text: Option<String> = row.get(1)?;
+            let cdata = text.unwrap_or_default().replace("]]>", "]]]]><![CDATA[>");
+            writeln!(
+                writer,
+                "    <ar><k>{}</k><def><![CDATA[{}]]></def></ar>",
+                escape_html(&name),
+                cdata
+            )?;
+            count += 1;
+        }
+        writeln!(writer, "  </lexicon>")?;
+        writeln!(writer, "</xdxf>")?;
+        writer.flush()?;
+        Ok(count)
+    }
+
+    /// Write an EPUB 3 dictionary. Entries are grouped into one content document per lowercased
+    /// initial letter (other initials share `other.xhtml`, placed last), every entry is an
+    /// `epub:type="dictentry"` article with the headword in `<dfn>`. Headwords are also listed
+    /// in the search key map `skm.xml`.
+    pub fn export_to_epub_dictionary(
+        &self,
+        dest: &str,
+        title: &str,
+        language: &str,
+        target_language: &str,
+    ) -> Result<(), Box<dyn Error>> {
+        self.ensure_entry_type("EPUB")?;
+        let mut groups: BTreeMap<String, Vec<String>> = BTreeMap::new();
+        let mut other: Vec<String> = Vec::new();
+        {
+            let mut stmt = self.conn.prepare(
+                format!("SELECT DISTINCT substr(name, 1, 1) FROM {}", ENTRY_TABLE).as_str(),
+            )?;
+            let mut rows = stmt.query(params![])?;
+            while let Some(row) = rows.next()? {
+                let initial: Option<String> = row.get(0)?;
+                let c = match initial.as_deref().and_then(|v| v.chars().next()) {
+                    Some(v) => v,
+                    None => continue,
+                };
+                if c.is_alphabetic() {
+                    groups
+                        .entry(c.to_lowercase().collect())
+                        .or_default()
+                        .push(c.to_string());
+                } else {
+                    other.push(c.to_string());
+                }
+            }
+        }
+        // (file name, label, initials)
+        let mut documents: Vec<(String, String, Vec<String>)> = groups
+            .into_iter()
+            .map(|(key, initials)| {
+                let file = if key.chars().all(|c| c.is_ascii_lowercase()) {
+                    format!("{}.xhtml", key)
+                } else {
+                    format!(
+                        "u{:04x}.xhtml",
+                        key.chars().next().unwrap_or_default() as u32
+                    )
+                };
+                (file, key.to_uppercase(), initials)
+            })
+            .collect();
+        if !other.is_empty() {
+            documents.push((String::from("other.xhtml"), String::from("#"), other));
+        }
+
+        let mut zip = ZipWriter::new(fs::File::create(dest)?);
+        let stored = SimpleFileOptions::default().compression_method(CompressionMethod::Stored);
+        let deflated = SimpleFileOptions::default().compression_method(CompressionMethod::Deflated);
+        // mimetype must be the first and uncompressed
+        zip.start_file("mimetype", stored)?;
+        zip.write_all(b"application/epub+zip")?;
+        zip.start_file("META-INF/container.xml", deflated)?;
+        zip.write_all(
+            br#"<?xml version="1.0" encoding="UTF-8"?>
+<container version="1.0" xmlns="urn:oasis:names:tc:opendocument:xmlns:container">
+  <rootfiles>
+    <rootfile full-path="OEBPS/content.opf" media-type="application/oebps-package+xml"/>
+  </rootfiles>
+</container>
+"#,
+        )?;
+
+        let title = escape_html(title);
+        let language = escape_html(language);
+        let target_language = escape_html(target_language);
+        let identifier = blake3::hash(format!("{}\0{}", title, language).as_bytes()).to_hex();
+        let mut manifest = String::new();
+        let mut spine = String::new();
+        let mut toc = String::new();
+        for (i, (file, label, _)) in documents.iter().enumerate() {
+            manifest.push_str(&format!(
+                "    <item id=\"d{}\" href=\"{}\" media-type=\"application/xhtml+xml\"/>\n",
+                i, file
+            ));
+            spine.push_str(&format!("    <itemref idref=\"d{}\"/>\n", i));
+            toc.push_str(&format!(
+                "      <li><a href=\"{}\">{}</a></li>\n",
+                file,
+                escape_html(label)
+            ));
+        }
+        zip.start_file("OEBPS/content.opf", deflated)?;
+        write!(
+            zip,
+            r#"<?xml version="1.0" encoding="UTF-8"?>
+<package xmlns="http://www.idpf.org/2007/opf" version="3.0" unique-identifier="uid" xml:lang="{language}">
+  <metadata xmlns:dc="http://purl.org/dc/elements/1.1/">
+    <dc:identifier id="uid">urn:beluga:{identifier}</dc:identifier>
+    <dc:title>{title}</dc:title>
+    <dc:language>{language}</dc:language>
+    <dc:type>dictionary</dc:type>
+    <meta property="source-language">{language}</meta>
+    <meta property="target-language">{target_language}</meta>
+    <meta property="dcterms:modified">{modified}</meta>
+    <meta name="generator" content="{generator}"/>
+  </metadata>
+  <manifest>
+    <item id="nav" href="nav.xhtml" media-type="application/xhtml+xml" properties="nav"/>
+    <item id="skm" href="skm.xml" media-type="application/vnd.epub.search-key-map+xml" properties="search-key-map dictionary"/>
+{manifest}  </manifest>
+  <spine>
+{spine}  </spine>
+</package>
+"#,
+            identifier = &identifier.as_str()[..32],
+            modified = rfc3339_now(),
+            generator = generator(),
+        )?;
+        zip.start_file("OEBPS/nav.xhtml", deflated)?;
+        write!(
+            zip,
+            r#"<?xml version="1.0" encoding="UTF-8"?>
+<!DOCTYPE html>
+<html xmlns="http://www.w3.org/1999/xhtml" xmlns:epub="http://www.idpf.org/2007/ops" xml:lang="{language}" lang="{language}">
+<head><title>{title}</title></head>
+<body>
+  <nav epub:type="toc">
+    <h1>{title}</h1>
+    <ol>
+{toc}    </ol>
+  </nav>
+</body>
+</html>
+"#
+        )?;
+
+        let mut skm = String::new();
+        let mut id = 0;
+        for (file, label, initials) in &documents {
+            zip.start_file(format!("OEBPS/{}", file), deflated)?;
+            write!(
+                zip,
+                r#"<?xml version="1.0" encoding="UTF-8"?>
+<!DOCTYPE html>
+<html xmlns="http://www.w3.org/1999/xhtml" xmlns:epub="http://www.idpf.org/2007/ops" xml:lang="{language}" lang="{language}">
+<head><title>{label}</title></head>
+<body>
+<section epub:type="dictionary">
+<h1>{label}</h1>
+"#,
+                label = escape_html(label)
+            )?;
+            let placeholders = vec!["?"; initials.len()].join(", ");
+            let mut stmt = self.conn.prepare(
+                format!(
+                    "SELECT name, text FROM {} WHERE substr(name, 1, 1) IN ({}) ORDER BY name ASC",
+                    ENTRY_TABLE, placeholders
+                )
+                .as_str(),
+            )?;
+            let mut rows = stmt.query(params_from_iter(initials.iter()))?;
+            while let Some(row) = rows.next()? {
+                let name: String = row.get(0)?;
+                let text: Option<String> = row.get(1)?;
+                id += 1;
+                writeln!(
+                    zip,
+                    r#"<article id="e{}" epub:type="dictentry"><dl><dt><dfn>{}</dfn></dt><dd>{}</dd></dl></article>"#,
+                    id,
+                    escape_html(&name),
+                    to_xhtml(text.as_deref().unwrap_or_default())
+                )?;
+                skm.push_str(&format!(
+                    "  <search-key-group href=\"{}#e{}\"><match value=\"{}\"/></search-key-group>\n",
+                    file,
+                    id,
+                    escape_html(&name)
+                ));
+            }
+            zip.write_all(b"</section>\n</body>\n</html>\n")?;
+        }
+        zip.start_file("OEBPS/skm.xml", deflated)?;
+        write!(
+            zip,
+            r#"<?xml version="1.0" encoding="UTF-8"?>
+<search-key-map xmlns="http://www.idpf.org/2007/ops" xml:lang="{language}">
+{skm}</search-key-map>
+"#
+        )?;
+        zip.finish()?;
+        Ok(())
+    }
+
+    /// Write CSV with the columns and transformations of a TOML schema, see [`CsvSchema`].
+    /// Any column of the entry table can be a source, blobs are written as lossy UTF-8.
+    pub fn export_to_csv_with_schema(
+        &self,
+        schema_path: &str,
+        writer: impl Write,
+    ) -> Result<usize, Box<dyn Error>> {
+        let schema = CsvSchema::from_toml(&fs::read_to_string(schema_path)?)?;
+        for column in &schema.columns {
+            if !self.has_column(ENTRY_TABLE, &column.source)? {
+                return Err(format!("Column not found: {}", column.source).into());
+            }
+        }
+        let mut csv = CsvWriter::with_delimiter(writer, schema.delimiter);
+        if schema.header {
+            let names: Vec<&str> = schema.columns.iter().map(|v| v.name.as_str()).collect();
+            csv.write_record(&names)?;
+        }
+        let sources: Vec<String> = schema
+            .columns
+            .iter()
+            .map(|v| format!("\"{}\"", v.source))
+            .collect();
+        let mut stmt = self.conn.prepare(
+            format!(
+                "SELECT {} FROM {} ORDER BY id ASC",
+                sources.join(", "),
+                ENTRY_TABLE
+            )
+            .as_str(),
+        )?;
+        let mut rows = stmt.query(params![])?;
+        let mut count = 0;
+        while let Some(row) = rows.next()? {
+            let mut fields = Vec::with_capacity(schema.columns.len());
+            for (i, column) in schema.columns.iter().enumerate() {
+                let value = match row.get_ref(i)? {
+                    ValueRef::Null => String::new(),
+                    ValueRef::Integer(v) => v.to_string(),
+                    ValueRef::Real(v) => v.to_string(),
+                    ValueRef::Text(v) | ValueRef::Blob(v) => String::from_utf8_lossy(v).to_string(),
+                };
+                fields.push(column.apply(&value));
+            }
+            csv.write_record(&fields)?;
+            count += 1;
+        }
+        csv.flush()?;
+        Ok(count)
+    }
+}
@@ -0,0 +1,467 @@
+use super::{entry_insert_sql, entry_update_sql, sha256_hex, update_entry, RawDict, ENTRY_TABLE};
+use crate::utils::escape_html;
+use crate::wikitext;
+use beluga_core::beluga::BelFileType;
+use calamine::{open_workbook, Data, Ods, Reader as _};
+use quick_xml::escape::resolve_predefined_entity;
+use quick_xml::events::Event;
+use quick_xml::Reader;
+use rusqlite::{params, OptionalExtension};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::error::Error;
+use std::io::{BufReader, Read};
+
+/// TEI elements rendered as `div`, the others become `span`.
+const TEI_BLOCK_ELEMENTS: [&str; 8] = ["cit", "def", "etym", "form", "note", "re", "sense", "xr"];
+
+#[derive(Deserialize)]
+struct OpenRussianWord {
+    bare: String,
+    accented: Option<String>,
+    translations_en: Option<String>,
+    #[serde(rename = "type")]
+    word_type: Option<String>,
+}
+
+#[derive(Default)]
+struct JmdictSense {
+    pos: Vec<String>,
+    glosses: Vec<String>,
+}
+
+/// `<!ENTITY name "value">` declarations of a DOCTYPE internal subset.
+fn parse_dtd_entities(doctype: &str) -> HashMap<String, String> {
+    let mut entities = HashMap::new();
+    let mut rest = doctype;
+    while let Some(i) = rest.find("<!ENTITY") {
+        rest = &rest[i + 8..];
+        let decl = match rest.find('>') {
+            Some(end) => &rest[..end],
+            None => break,
+        };
+        let mut parts = decl.trim().splitn(2, char::is_whitespace);
+        let name = parts.next().unwrap_or_default();
+        let value = parts.next().unwrap_or_default().trim();
+        let value = value
+            .strip_prefix('"')
+            .and_then(|v| v.strip_suffix('"'))
+            .or_else(|| value.strip_prefix('\'').and_then(|v| v.strip_suffix('\'')));
+        if let Some(value) = value {
+            entities.insert(name.to_string(), value.to_string());
+        }
+        rest = &rest[decl.len()..];
+    }
+    entities
+}
+
+impl RawDict {
+    fn ensure_entry_import(&self, format: &str) -> Result<(), Box<dyn Error>> {
+        if self.file_type != BelFileType::Entry {
+            return Err(format!("{} import only supports entry dictionaries", format).into());
+        }
+        Ok(())
+    }
+
+    /// Insert entries and flush, merging definitions that share a name.
+    fn insert_merged_entries(&mut self, entries: Vec<(String, String)>) -> usize {
+        let mut index: HashMap<String, usize> = HashMap::new();
+        let mut merged: Vec<(String, String)> = vec![];
+        for (name, html) in entries {
+            match index.get(&name) {
+                Some(&i) => merged[i].1.push_str(&html),
+                None => {
+                    index.insert(name.clone(), merged.len());
+                    merged.push((name, html));
+                }
+            }
+        }
+        let count = merged.len();
+        for (name, html) in merged {
+            self.insert_entry(&name, html.as_bytes());
+        }
+        self.flush_entry_cache();
+        count
+    }
+
+    /// Insert entries in one transaction, returns the number of new names. Definitions of names
+    /// that are already in the database are appended, except `@@@LINK=` redirects: an article
+    /// replaces a redirect and a redirect never replaces or extends an article.
+    fn merge_entries(&mut self, entries: &mut Vec<(String, String)>) -> rusqlite::Result<usize> {
+        let fingerprint = self.fingerprint;
+        let tx = self.conn.transaction()?;
+        let mut count = 0;
+        {
+            let mut select_stmt = tx.prepare(
+                format!("SELECT id, text FROM {} WHERE name = $1", ENTRY_TABLE).as_str(),
+            )?;
+            let mut insert_stmt = tx.prepare(entry_insert_sql("text", fingerprint).as_str())?;
+            let mut update_stmt = tx.prepare(entry_update_sql("text", fingerprint).as_str())?;
+            for (name, html) in entries.drain(..) {
+                let existing: Option<(i64, Option<String>)> = select_stmt
+                    .query_row(params![name], |row| Ok((row.get(0)?, row.get(1)?)))
+                    .optional()?;
+                let (id, text) = match existing {
+                    Some((id, text)) => (id, text.unwrap_or_default()),
+                    None => {
+                        if fingerprint {
+                            insert_stmt.execute(params![
+                                name,
+                                html,
+                                sha256_hex(html.as_bytes())
+                            ])?;
+                        } else {
+                            insert_stmt.execute(params![name, html])?;
+                        }
+                        count += 1;
+                        continue;
+                    }
+                };
+                let merged = match (text.starts_with("@@@LINK="), html.starts_with("@@@LINK=")) {
+                    (_, true) => continue,
+                    (true, false) => html,
+                    (false, false) => text + &html,
+                };
+                update_entry(&mut update_stmt, fingerprint, id, &merged, &merged)?;
+            }
+        }
+        tx.commit()?;
+        Ok(count)
+    }
+
+    /// Import the JSON word list of Open Russian (https://github.com/Badestrand/russian-dictionary).
+    /// Words with the same `bare` form are merged into one entry.
+    pub fn import_from_open_russian(&mut self, reader: impl Read) -> Result<usize, Box<dyn Error>> {
+        self.ensure_entry_import("Open Russian")?;
+        let words: Vec<OpenRussianWord> = serde_json::from_reader(reader)?;
+        let mut entries: Vec<(String, String)> = Vec::with_capacity(words.len());
+        for word in words {
+            if word.bare.is_empty() {
+                continue;
+            }
+            // stress is marked by an apostrophe after the vowel, use a combining acute accent instead
+            let headword = match &word.accented {
+                Some(v) if !v.is_empty() => v.replace('\'', "\u{301}"),
+                _ => word.bare.clone(),
+            };
+            let mut html = format!("<div class=\"word\"><h3>{}</h3>", escape_html(&headword));
+            if let Some(t) = word.word_type.filter(|v| !v.is_empty()) {
+                html.push_str(&format!("<p class=\"type\">{}</p>", escape_html(&t)));
+            }
+            if let Some(t) = word.translations_en {
+                let translations: Vec<&str> = t
+                    .split(';')
+                    .map(|v| v.trim())
+                    .filter(|v| !v.is_empty())
+                    .collect();
+                if !translations.is_empty() {
+                    html.push_str("<ol>");
+                    for v in translations {
+                        html.push_str(&format!("<li>{}</li>", escape_html(v)));
+                    }
+                    html.push_str("</ol>");
+                }
+            }
+            html.push_str("</div>");
+            entries.push((word.bare, html));
+        }
+        Ok(self.insert_merged_entries(entries))
+    }
+
+    /// Import FreeDict TEI XML. The first `<orth>` of every `<entry>` is the entry name,
+    /// the entry body is rendered as HTML with TEI element names as class names.
+    pub fn import_from_freedict(&mut self, reader: impl Read) -> Result<usize, Box<dyn Error>> {
+        self.ensure_entry_import("FreeDict")?;
+        let mut xml = Reader::from_reader(BufReader::new(reader));
+        let mut buf = Vec::new();
+        let mut entries: Vec<(String, String)> = vec![];
+        // depth inside <entry>, 0 means outside of any entry
+        let mut depth = 0;
+        let mut tags: Vec<&str> = vec![];
+        let mut html = String::new();
+        let mut orth: Option<String> = None;
+        let mut orth_depth: Option<usize> = None;
+        loop {
+            match xml.read_event_into(&mut buf)? {
+                Event::Start(e) => {
+                    let name = String::from_utf8_lossy(e.local_name().as_ref()).into_owned();
+                    if depth == 0 {
+                        if name == "entry" {
+                            depth = 1;
+                            html.clear();
+                            orth = None;
+                        }
+                    } else {
+                        depth += 1;
+                        let tag = if TEI_BLOCK_ELEMENTS.contains(&name.as_str()) {
+                            "div"
+                        } else {
+                            "span"
+                        };
+                        html.push_str(&format!("<{} class=\"{}\">", tag, escape_html(&name)));
+                        tags.push(tag);
+                        if name == "orth" && orth.is_none() {
+                            orth = Some(String::new());
+                            orth_depth = Some(depth);
+                        }
+                    }
+                }
+                Event::End(_) => {
+                    if depth == 1 {
+                        depth = 0;
+                        if let Some(name) = orth.take() {
+                            let name = name.trim();
+                            if !name.is_empty() {
+                                entries.push((
+                                    name.to_string(),
+                                    format!("<div class=\"entry\">{}</div>", html),
+                                ));
+                            }
+                        }
+                    } else if depth > 1 {
+                        if orth_depth == Some(depth) {
+                            orth_depth = None;
+                        }
+                        depth -= 1;
+                        if let Some(tag) = tags.pop() {
+                            html.push_str(&format!("</{}>", tag));
+                        }
+                    }
+                }
+                Event::Text(e) if depth > 0 => {
+                    let text = e.unescape()?;
+                    if let (Some(_), Some(v)) = (orth_depth, orth.as_mut()) {
+                        v.push_str(&text);
+                    }
+                    html.push_str(&escape_html(&text));
+                }
+                Event::CData(e) if depth > 0 => {
+                    let text = String::from_utf8_lossy(&e).into_owned();
+                    if let (Some(_), Some(v)) = (orth_depth, orth.as_mut()) {
+                        v.push_str(&text);
+                    }
+                    html.push_str(&escape_html(&text));
+                }
+                Event::Eof => break,
+                _ => {}
+            }
+            buf.clear();
+        }
+        Ok(self.insert_merged_entries(entries))
+    }
+
+    /// Import JMdict XML, `JMdict_e.gz` has to be decompressed by the reader. One entry per `<keb>`, or per `<reb>`
+    /// for kana-only words, with all senses rendered as an ordered list.
+    pub fn import_from_jmdict_xml(&mut self, reader: impl Read) -> Result<usize, Box<dyn Error>> {
+        self.ensure_entry_import("JMdict")?;
+        let mut xml = Reader::from_reader(BufReader::new(reader));
+        let mut buf = Vec::new();
+        let mut entries: Vec<(String, String)> = vec![];
+        let mut entities: HashMap<String, String> = HashMap::new();
+        let mut element = String::new();
+        let mut kebs: Vec<String> = vec![];
+        let mut rebs: Vec<String> = vec![];
+        let mut senses: Vec<JmdictSense> = vec![];
+        loop {
+            match xml.read_event_into(&mut buf)? {
+                Event::DocType(e) => {
+                    entities = parse_dtd_entities(&String::from_utf8_lossy(&e));
+                }
+                Event::Start(e) => {
+                    element = String::from_utf8_lossy(e.local_name().as_ref()).into_owned();
+                    match element.as_str() {
+                        "entry" => {
+                            kebs.clear();
+                            rebs.clear();
+                            senses.clear();
+                        }
+                        "keb" => kebs.push(String::new()),
+                        "reb" => rebs.push(String::new()),
+                        "sense" => senses.push(JmdictSense::default()),
+                        "pos" => {
+                            if let Some(sense) = senses.last_mut() {
+                                sense.pos.push(String::new());
+                            }
+                        }
+                        "gloss" => {
+                            if let Some(sense) = senses.last_mut() {
+                                sense.glosses.push(String::new());
+                            }
+                        }
+                        _ => {}
+                    }
+                }
+                Event::Text(e) => {
+                    let text = e.unescape_with(|v| {
+                        entities
+                            .get(v)
+                            .map(|v| v.as_str())
+                            .or_else(|| resolve_predefined_entity(v))
+                    })?;
+                    let target = match element.as_str() {
+                        "keb" => kebs.last_mut(),
+                        "reb" => rebs.last_mut(),
+                        "pos" => senses.last_mut().and_then(|v| v.pos.last_mut()),
+                        "gloss" => senses.last_mut().and_then(|v| v.glosses.last_mut()),
+                        _ => None,
+                    };
+                    if let Some(target) = target {
+                        target.push_str(&text);
+                    }
+                }
+                Event::End(e) => {
+                    element.clear();
+                    if e.local_name().as_ref() != b"entry" {
+                        buf.clear();
+                        continue;
+                    }
+                    let mut html = String::from("<div class=\"jmdict\">");
+                    if !rebs.is_empty() {
+                        html.push_str(&format!(
+                            "<p class=\"reading\">{}</p>",
+                            escape_html(&rebs.join("・"))
+                        ));
+                    }
+                    html.push_str("<ol>");
+                    for sense in &senses {
+                        html.push_str("<li>");
+                        if !sense.pos.is_empty() {
+                            html.push_str(&format!(
+                                "<span class=\"pos\">{}</span> ",
+                                escape_html(&sense.pos.join(", "))
+                            ));
+                        }
+                        html.push_str(&escape_html(&sense.glosses.join("; ")));
+                        html.push_str("</li>");
+                    }
+                    html.push_str("</ol></div>");
+                    let names = if kebs.is_empty() { &rebs } else { &kebs };
+                    for name in names {
+                        let name = name.trim();
+                        if !name.is_empty() {
+                            entries.push((
+                                name.to_string(),
+                                format!("<h3>{}</h3>{}", escape_html(name), html),
+                            ));
+                        }
+                    }
+                }
+                Event::Eof => break,
+                _ => {}
+            }
+            buf.clear();
+        }
+        Ok(self.insert_merged_entries(entries))
+    }
+
+    /// Import the first sheet of an OpenDocument spreadsheet, column A is the entry name and
+    /// column B the definition. The first row is a header and skipped.
+    pub fn import_from_ods(&mut self, path: &str) -> Result<usize, Box<dyn Error>> {
+        self.ensure_entry_import("ODS")?;
+        let mut workbook: Ods<_> = open_workbook(path)?;
+        let range = workbook
+            .worksheet_range_at(0)
+            .ok_or("The spreadsheet has no sheet")??;
+        let mut entries: Vec<(String, String)> = vec![];
+        for row in range.rows().skip(1) {
+            let name = match row.first() {
+                Some(Data::Empty) | None => continue,
+                Some(v) => v.to_string(),
+            };
+            let name = name.trim();
+            if name.is_empty() {
+                continue;
+            }
+            let definition = match row.get(1) {
+                Some(Data::Empty) | None => String::new(),
+                Some(v) => v.to_string(),
+            };
+            entries.push((name.to_string(), definition));
+        }
+        Ok(self.insert_merged_entries(entries))
+    }
+
+    /// Import a MediaWiki XML dump of Wiktionary (decompress `*-pages-articles.xml.bz2` first).
+    /// Only pages of the main namespace are imported, redirects become `@@@LINK=` entries.
+    /// Pages are written in batches while reading, see `merge_entries` for duplicate titles.
+    pub fn import_from_wiktionary_dump(
+        &mut self,
+        reader: impl Read,
+    ) -> Result<usize, Box<dyn Error>> {
+        self.ensure_entry_import("Wiktionary")?;
+        let mut xml = Reader::from_reader(BufReader::new(reader));
+        let mut buf = Vec::new();
+        let mut entries: Vec<(String, String)> = Vec::with_capacity(self.cache_size);
+        let mut count = 0;
+        let mut element = String::new();
+        let mut title = String::new();
+        let mut ns = String::new();
+        let mut text = String::new();
+        let mut redirect: Option<String> = None;
+        loop {
+            match xml.read_event_into(&mut buf)? {
+                Event::Start(e) => {
+                    element = String::from_utf8_lossy(e.local_name().as_ref()).into_owned();
+                    match element.as_str() {
+                        "page" => {
+                            title.clear();
+                            ns.clear();
+                            text.clear();
+                            redirect = None;
+                        }
+                        // keep the text of the last revision only
+                        "text" => text.clear(),
+                        _ => {}
+                    }
+                }
+                Event::Empty(e) => match e.local_name().as_ref() {
+                    b"redirect" => {
+                        if let Some(attr) = e.try_get_attribute("title")? {
+                            redirect = Some(attr.unescape_value()?.into_owned());
+                        }
+                    }
+                    // `<text bytes="0" ... />` of an empty revision
+                    b"text" => text.clear(),
+                    _ => {}
+                },
+                Event::Text(e) => {
+                    let target = match element.as_str() {
+                        "title" => &mut title,
+                        "ns" => &mut ns,
+                        "text" => &mut text,
+                        _ => {
+                            buf.clear();
+                            continue;
+                        }
+                    };
+                    target.push_str(&e.unescape()?);
+                }
+                Event::End(e) => {
+                    element.clear();
+                    if e.local_name().as_ref() != b"page" || ns.trim() != "0" {
+                        buf.clear();
+                        continue;
+                    }
+                    let name = title.trim();
+                    if name.is_empty() {
+                        buf.clear();
+                        continue;
+                    }
+                    let html = match redirect.take() {
+                        Some(target) => format!("@@@LINK={}", target),
+                        None => wikitext::to_html(&text),
+                    };
+                    entries.push((name.to_string(), html));
+                    if entries.len() >= self.cache_size {
+                        count += self.merge_entries(&mut entries)?;
+                    }
+                }
+                Event::Eof => break,
+                _ => {}
+            }
+            buf.clear();
+        }
+        count += self.merge_entries(&mut entries)?;
+        Ok(count)
+    }
+}
@@ -0,0 +1,178 @@
+use crate::utils::strip_html;
+use regex::Regex;
+use scraper::{Html, Selector};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::error::Error;
+
+#[derive(Deserialize)]
+struct EntrySchemaFile {
+    #[serde(default)]
+    fields: BTreeMap<String, String>,
+}
+
+/// Structured fields extracted from definition HTML, configured by a TOML file:
+///
+/// ```toml
+/// [fields]
+/// pron = "span.pron"
+/// pos = ".pos"
+/// ```
+pub struct EntrySchema {
+    fields: Vec<(String, Selector)>,
+}
+
+impl EntrySchema {
+    pub fn new() -> Self {
+        Self { fields: vec![] }
+    }
+
+    pub fn from_toml(content: &str) -> Result<Self, Box<dyn Error>> {
+        let file: EntrySchemaFile = toml::from_str(content)?;
+        let mut fields = vec![];
+        for (name, selector) in file.fields {
+            if name == "html" {
+                return Err("`html` is reserved for the definition".into());
+            }
+            let selector = Selector::parse(&selector)
+                .map_err(|e| format!("Invalid selector of `{}`: {}", name, e))?;
+            fields.push((name, selector));
+        }
+        Ok(Self { fields })
+    }
+
+    /// `{"html": "...", field: "..."}`, fields without a match are omitted.
+    pub fn to_json(&self, html: &str) -> String {
+        let mut value = serde_json::Map::new();
+        value.insert("html".to_string(), html.into());
+        if !self.fields.is_empty() {
+            let doc = Html::parse_fragment(html);
+            for (name, selector) in &self.fields {
+                let text = doc
+                    .select(selector)
+                    .next()
+                    .map(|v| v.text().collect::<String>().trim().to_string());
+                if let Some(text) = text.filter(|v| !v.is_empty()) {
+                    value.insert(name.clone(), text.into());
+                }
+            }
+        }
+        serde_json::Value::Object(value).to_string()
+    }
+}
+
+#[derive(Deserialize)]
+struct CsvSchemaFile {
+    delimiter: Option<char>,
+    #[serde(default = "default_header")]
+    header: bool,
+    columns: Vec<CsvColumnFile>,
+}
+
+fn default_header() -> bool {
+    true
+}
+
+#[derive(Deserialize)]
+struct CsvColumnFile {
+    name: String,
+    source: String,
+    #[serde(default)]
+    strip_html: bool,
+    truncate: Option<usize>,
+    #[serde(default)]
+    regex_replace: Vec<(String, String)>,
+}
+
+pub struct CsvColumn {
+    pub name: String,
+    pub source: String,
+    strip_html: bool,
+    truncate: Option<usize>,
+    regex_replace: Vec<(Regex, String)>,
+}
+
+impl CsvColumn {
+    /// Strip HTML, then replace, then truncate to `truncate` characters.
+    pub fn apply(&self, value: &str) -> String {
+        let mut value = if self.strip_html {
+            strip_html(value)
+        } else {
+            value.to_string()
+        };
+        for (regex, replacement) in &self.regex_replace {
+            value = regex.replace_all(&value, replacement.as_str()).into_owned();
+        }
+        if let Some(max) = self.truncate {
+            if let Some((i, _)) = value.char_indices().nth(max) {
+                value.truncate(i);
+            }
+        }
+        value
+    }
+}
+
+/// CSV layout configured by a TOML file, columns are written in the listed order:
+///
+/// ```toml
+/// delimiter = ","
+/// header = true
+///
+/// [[columns]]
+/// name = "word"
+/// source = "name"
+///
+/// [[columns]]
+/// name = "definition"
+/// source = "text"
+/// strip_html = true
+/// truncate = 200
+/// regex_replace = [["\\s+", " "]]
+/// ```
+pub struct CsvSchema {
+    pub delimiter: char,
+    pub header: bool,
+    pub columns: Vec<CsvColumn>,
+}
+
+impl CsvSchema {
+    pub fn from_toml(content: &str) -> Result<Self, Box<dyn Error>> {
+        let file: CsvSchemaFile = toml::from_str(content)?;
+        if file.columns.is_empty() {
+            return Err("CSV schema has no columns".into());
+        }
+        let delimiter = file.delimiter.unwrap_or(',');
+        if matches!(delimiter, '"' | '\r' | '\n') {
+            return Err(format!("Invalid CSV delimiter: {:?}", delimiter).into());
+        }
+        let mut columns = vec![];
+        for column in file.columns {
+            if column.source.is_empty()
+                || !column
+                    .source
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || c == '_')
+            {
+                return Err(format!("Invalid source column: {}", column.source).into());
+            }
+            let mut regex_replace = vec![];
+            for (pattern, replacement) in column.regex_replace {
+                let regex = Regex::new(&pattern)
+                    .map_err(|e| format!("Invalid regex of `{}`: {}", column.name, e))?;
+                regex_replace.push((regex, replacement));
+            }
+            columns.push(CsvColumn {
+                name: column.name,
+                source: column.source,
+                strip_html: column.strip_html,
+                truncate: column.truncate,
+                regex_replace,
+            });
+        }
+        Ok(Self {
+            delimiter,
+            header: file.header,
+            columns,
+        })
+    }
+}
@@ -0,0 +1,416 @@
+use crate::raw::{RawDict, RawOptions};
+use crate::utils::escape_html;
+use crate::wikitext;
+use beluga_core::beluga::{BelFileType, Beluga, Metadata};
+use flate2::read::GzDecoder;
+use pbr::ProgressBar;
+use regex::Regex;
+use std::collections::{HashMap, HashSet};
+use std::error::Error;
+use std::fs::{self, File};
+use std::io::Read;
+use std::path::Path;
+
+/// Read a file, or its gzip (dictzip) compressed sibling `{path}.{gz_ext}`.
+fn read_maybe_gz(path: &str, gz_ext: &str) -> Result<Option<Vec<u8>>, Box<dyn Error>> {
+    if Path::new(path).exists() {
+        return Ok(Some(fs::read(path)?));
+    }
+    let gz = format!("{}.{}", path, gz_ext);
+    if Path::new(&gz).exists() {
+        let mut data = vec![];
+        GzDecoder::new(File::open(gz)?).read_to_end(&mut data)?;
+        return Ok(Some(data));
+    }
+    Ok(None)
+}
+
+/// Split a null terminated UTF-8 string at the start of `data`.
+fn read_cstr(data: &[u8]) -> Result<(String, &[u8]), Box<dyn Error>> {
+    let end = data
+        .iter()
+        .position(|&b| b == 0)
+        .ok_or("Unterminated string")?;
+    Ok((
+        String::from_utf8_lossy(&data[..end]).to_string(),
+        &data[end + 1..],
+    ))
+}
+
+fn read_u32(data: &[u8]) -> Result<(u32, &[u8]), Box<dyn Error>> {
+    if data.len() < 4 {
+        return Err("Unexpected end of data".into());
+    }
+    let (v, rest) = data.split_at(4);
+    Ok((u32::from_be_bytes(v.try_into()?), rest))
+}
+
+fn read_u64(data: &[u8]) -> Result<(u64, &[u8]), Box<dyn Error>> {
+    if data.len() < 8 {
+        return Err("Unexpected end of data".into());
+    }
+    let (v, rest) = data.split_at(8);
+    Ok((u64::from_be_bytes(v.try_into()?), rest))
+}
+
+/// (word, offset, size) of a definition in `.dict`
+type IdxRecord = (String, u64, u32);
+
+/// `.idx` records: null terminated word, 32 or 64 bit offset and 32 bit size, big endian.
+fn parse_idx(data: &[u8], offset64: bool) -> Result<Vec<IdxRecord>, Box<dyn Error>> {
+    let mut words = vec![];
+    let mut rest = data;
+    while !rest.is_empty() {
+        let (word, r) = read_cstr(rest)?;
+        let (offset, r) = if offset64 {
+            read_u64(r)?
+        } else {
+            let (v, r) = read_u32(r)?;
+            (v as u64, r)
+        };
+        let (size, r) = read_u32(r)?;
+        words.push((word, offset, size));
+        rest = r;
+    }
+    Ok(words)
+}
+
+/// `.syn` records: null terminated synonym and 32 bit index into `.idx`.
+fn parse_syn(data: &[u8]) -> Result<Vec<(String, u32)>, Box<dyn Error>> {
+    let mut synonyms = vec![];
+    let mut rest = data;
+    while !rest.is_empty() {
+        let (word, r) = read_cstr(rest)?;
+        let (index, r) = read_u32(r)?;
+        synonyms.push((word, index));
+        rest = r;
+    }
+    Ok(synonyms)
+}
+
+fn text_to_html(text: &str) -> String {
+    let lines: Vec<String> = text.trim_end().lines().map(escape_html).collect();
+    format!("<p>{}</p>", lines.join("<br>"))
+}
+
+/// HTML of a definition field, binary fields (audio, pictures) are dropped.
+fn field_to_html(kind: u8, data: &[u8]) -> String {
+    let text = String::from_utf8_lossy(data);
+    match kind {
+        b'h' | b'g' | b'x' => text.to_string(),
+        b't' => format!(
+            "<p><span class=\"phonetic\">[{}]</span></p>",
+            escape_html(&text)
+        ),
+        b'w' => wikitext::to_html(&text),
+        b'm' | b'l' | b'y' | b'k' => text_to_html(&text),
+        _ => String::new(),
+    }
+}
+
+/// StarDict dictionary: `.ifo` metadata, `.idx` (or `.idx.gz`) word index, `.dict` (or `.dict.dz`)
+/// definitions and the optional `.syn` synonyms.
+pub struct StarDict {
+    pub info: HashMap<String, String>,
+    words: Vec<IdxRecord>,
+    synonyms: Vec<(String, u32)>,
+    dict: Vec<u8>,
+    filter: Option<Regex>,
+    offset: u64,
+    limit: Option<u64>,
+}
+
+impl StarDict {
+    pub fn new(ifo_path: &str) -> Result<Self, Box<dyn Error>> {
+        let ifo = fs::read_to_string(ifo_path)?;
+        let mut lines = ifo.lines();
+        if lines.next().map(|v| v.trim()) != Some("StarDict's dict ifo file") {
+            return Err("Invalid StarDict ifo file".into());
+        }
+        let info: HashMap<String, String> = lines
+            .filter_map(|v| v.split_once('='))
+            .map(|(k, v)| (k.trim().to_string(), v.trim().to_string()))
+            .collect();
+        let base = ifo_path.strip_suffix(".ifo").unwrap_or(ifo_path);
+
+        let offset64 = info.get("idxoffsetbits").map(|v| v.as_str()) == Some("64");
+        let idx = read_maybe_gz(&format!("{}.idx", base), "gz")?.ok_or("Missing .idx file")?;
+        let words = parse_idx(&idx, offset64)?;
+        let synonyms = match read_maybe_gz(&format!("{}.syn", base), "gz")? {
+            Some(syn) => parse_syn(&syn)?,
+            None => vec![],
+        };
+
+        let dict = read_maybe_gz(&format!("{}.dict", base), "dz")?.ok_or("Missing .dict file")?;
+        Ok(Self {
+            info,
+            words,
+            synonyms,
+            dict,
+            filter: None,
+            offset: 0,
+            limit: None,
+        })
+    }
+
+    /// Definition of the i-th word of the index as HTML.
+    /// With `sametypesequence` fields carry no type marks and the last one has no terminator
+    /// or size, otherwise every field starts with its type.
+    fn definition(&self, i: usize) -> Result<String, Box<dyn Error>> {
+        let (_, offset, size) = &self.words[i];
+        let start = usize::try_from(*offset)?;
+        let end = start
+            .checked_add(*size as usize)
+            .filter(|&end| end <= self.dict.len())
+            .ok_or("Definition out of range")?;
+        let data = &self.dict[start..end];
+        let types = self
+            .info
+            .get("sametypesequence")
+            .map(|v| v.as_bytes().to_vec());
+        let mut html = String::new();
+        let mut rest = data;
+        let mut n = 0;
+        while !rest.is_empty() {
+            let kind = match &types {
+                Some(types) => match types.get(n) {
+                    Some(&v) => v,
+                    None => break,
+                },
+                None => {
+                    let v = rest[0];
+                    rest = &rest[1..];
+                    v
+                }
+            };
+            let last = types.as_ref().is_some_and(|v| n + 1 == v.len());
+            let field = if last {
+                let v = rest;
+                rest = &[];
+                v
+            } else if kind.is_ascii_uppercase() {
+                let (len, r) = read_u32(rest)?;
+                let len = (len as usize).min(r.len());
+                rest = &r[len..];
+                &r[..len]
+            } else {
+                let end = rest.iter().position(|&b| b == 0).unwrap_or(rest.len());
+                let v = &rest[..end];
+                rest = rest.get(end + 1..).unwrap_or_default();
+                v
+            };
+            html.push_str(&field_to_html(kind, field));
+            n += 1;
+        }
+        Ok(html)
+    }
+
+    /// Only convert words (and synonyms) whose names match. Words come from the index, so
+    /// definitions of skipped words are never read.
+    pub fn set_filter(&mut self, filter: Option<Regex>) {
+        self.filter = filter;
+    }
+
+    /// Skip the first `offset` words and convert at most `limit`, counted after the filter.
+    /// Synonyms are kept when their words are converted.
+    pub fn set_range(&mut self, offset: u64, limit: Option<u64>) {
+        self.offset = offset;
+        self.limit = limit;
+    }
+
+    fn is_included(&self, name: &str) -> bool {
+        self.filter.as_ref().is_none_or(|v| v.is_match(name))
+    }
+
+    /// Entries in index order, definitions of the same word are merged and synonyms link to
+    /// their converted words with `@@@LINK=`.
+    fn entries(&self) -> Result<Vec<(String, String)>, Box<dyn Error>> {
+        let mut index: HashMap<String, usize> = HashMap::new();
+        let mut entries: Vec<(String, String)> = Vec::with_capacity(self.words.len());
+        let mut skipped: HashSet<&str> = HashSet::new();
+        let mut matched: u64 = 0;
+        for i in 0..self.words.len() {
+            let word = &self.words[i].0;
+            if !self.is_included(word) || skipped.contains(word.as_str()) {
+                continue;
+            }
+            if !index.contains_key(word) {
+                if self.limit.is_some_and(|v| entries.len() as u64 >= v) {
+                    continue;
+                }
+                matched += 1;
+                if matched <= self.offset {
+                    skipped.insert(word);
+                    continue;
+                }
+            }
+            let html = self.definition(i)?;
+            match index.get(word) {
+                Some(&v) => entries[v].1.push_str(&html),
+                None => {
+                    index.insert(word.clone(), entries.len());
+                    entries.push((word.clone(), html));
+                }
+            }
+        }
+        for (synonym, i) in &self.synonyms {
+            if index.contains_key(synonym) || !self.is_included(synonym) {
+                continue;
+            }
+            if let Some((word, _, _)) = self
+                .words
+                .get(*i as usize)
+                .filter(|(word, _, _)| index.contains_key(word))
+            {
+                index.insert(synonym.clone(), entries.len());
+                entries.push((synonym.clone(), format!("@@@LINK={}", word)));
+            }
+        }
+        Ok(entries)
+    }
+
+    pub async fn to_beluga(&self, dest: &str) -> Result<(), Box<dyn Error>> {
+        let entries = self.entries()?;
+        let mut dict = Beluga::new(Metadata::new(), BelFileType::Entry);
+        let mut bar = ProgressBar::new(entries.len() as u64);
+        for (name, html) in entries {
+            dict.input_entry(name, html.into_bytes());
+            bar.inc();
+        }
+        bar.finish();
+        dict.save(dest);
+        Ok(())
+    }
+
+    pub async fn to_raw(&self, dest: &str, options: &RawOptions) -> Result<(), Box<dyn Error>> {
+        let entries = self.entries()?;
+        let mut raw = RawDict::new_with_options(dest, options);
+        let mut bar = ProgressBar::new(entries.len() as u64);
+        for (name, html) in entries {
+            raw.insert_entry(&name, html.as_bytes());
+            bar.inc();
+        }
+        bar.finish();
+        raw.flush_entry_cache();
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn idx_record(word: &str, offset: u32, size: u32) -> Vec<u8> {
+        let mut data = word.as_bytes().to_vec();
+        data.push(0);
+        data.extend(offset.to_be_bytes());
+        data.extend(size.to_be_bytes());
+        data
+    }
+
+    fn dict(types: Option<&str>, words: Vec<(&str, u64, u32)>, data: &[u8]) -> StarDict {
+        let mut info = HashMap::new();
+        if let Some(v) = types {
+            info.insert(String::from("sametypesequence"), v.to_string());
+        }
+        StarDict {
+            info,
+            words: words
+                .into_iter()
+                .map(|(w, o, s)| (w.to_string(), o, s))
+                .collect(),
+            synonyms: vec![],
+            dict: data.to_vec(),
+            filter: None,
+            offset: 0,
+            limit: None,
+        }
+    }
+
+    #[test]
+    fn idx() {
+        let mut data = idx_record("apple", 0, 5);
+        data.extend(idx_record("日本", 5, 12));
+        assert_eq!(
+            parse_idx(&data, false).unwrap(),
+            [(String::from("apple"), 0, 5), (String::from("日本"), 5, 12)]
+        );
+        assert!(parse_idx(&data[..data.len() - 1], false).is_err());
+        assert!(parse_idx(b"apple", false).is_err());
+    }
+
+    #[test]
+    fn idx_64bit_offsets() {
+        let mut data = b"big\0".to_vec();
+        data.extend((1u64 << 40).to_be_bytes());
+        data.extend(7u32.to_be_bytes());
+        assert_eq!(
+            parse_idx(&data, true).unwrap(),
+            [(String::from("big"), 1 << 40, 7)]
+        );
+    }
+
+    #[test]
+    fn syn() {
+        let mut data = b"pomme\0".to_vec();
+        data.extend(0u32.to_be_bytes());
+        data.extend(b"malus\0");
+        data.extend(1u32.to_be_bytes());
+        assert_eq!(
+            parse_syn(&data).unwrap(),
+            [(String::from("pomme"), 0), (String::from("malus"), 1)]
+        );
+        assert!(parse_syn(b"x\0\0\0").is_err());
+    }
+
+    #[test]
+    fn definition_with_sametypesequence() {
+        // the last field has no terminator
+        let dict = dict(Some("tm"), vec![("apple", 0, 10)], b"ap\0a fruit");
+        assert_eq!(
+            dict.definition(0).unwrap(),
+            "<p><span class=\"phonetic\">[ap]</span></p><p>a fruit</p>"
+        );
+    }
+
+    #[test]
+    fn definition_with_type_marks() {
+        let mut data = b"ha <b>fruit</b>\0W".to_vec();
+        data.extend(3u32.to_be_bytes());
+        data.extend(b"wavmline\0");
+        let dict = dict(None, vec![("apple", 0, data.len() as u32)], &data);
+        assert_eq!(dict.definition(0).unwrap(), "a <b>fruit</b><p>line</p>");
+    }
+
+    #[test]
+    fn definition_out_of_range() {
+        let dict = dict(
+            Some("m"),
+            vec![("apple", 2, 4), ("pear", u64::MAX, 2)],
+            b"apple",
+        );
+        assert!(dict.definition(0).is_err());
+        assert!(dict.definition(1).is_err());
+    }
+
+    #[test]
+    fn synonyms_link_to_words() {
+        let mut dict = dict(
+            Some("m"),
+            vec![("apple", 0, 5), ("apple", 5, 4), ("pear", 9, 4)],
+            b"fruittreegood",
+        );
+        dict.synonyms = vec![(String::from("pomme"), 1), (String::from("pear"), 0)];
+        assert_eq!(
+            dict.entries().unwrap(),
+            [
+                (
+                    String::from("apple"),
+                    String::from("<p>fruit</p><p>tree</p>")
+                ),
+                (String::from("pear"), String::from("<p>good</p>")),
+                (String::from("pomme"), String::from("@@@LINK=apple")),
+            ]
+        );
+    }
+}
@@ -0,0 +1,257 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+pub fn escape_html(text: &str) -> String {
+    let mut s = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => s.push_str("&amp;"),
+            '<' => s.push_str("&lt;"),
+            '>' => s.push_str("&gt;"),
+            '"' => s.push_str("&quot;"),
+            '\'' => s.push_str("&#39;"),
+            _ => s.push(c),
+        }
+    }
+    s
+}
+
+/// Elements without end tags.
+pub const VOID_ELEMENTS: [&str; 13] = [
+    "area", "base", "br", "col", "embed", "hr", "img", "input", "link", "meta", "source", "track",
+    "wbr",
+];
+
+const BLOCK_TAGS: [&str; 22] = [
+    "address",
+    "blockquote",
+    "br",
+    "dd",
+    "div",
+    "dl",
+    "dt",
+    "h1",
+    "h2",
+    "h3",
+    "h4",
+    "h5",
+    "h6",
+    "hr",
+    "li",
+    "ol",
+    "p",
+    "pre",
+    "table",
+    "td",
+    "tr",
+    "ul",
+];
+
+/// Strip tags, decode entities and collapse whitespace. Content of `script` and `style` is dropped.
+pub fn strip_html(html: &str) -> String {
+    let mut text = String::with_capacity(html.len());
+    let mut rest = html;
+    while let Some(start) = rest.find('<') {
+        text.push_str(&rest[..start]);
+        rest = &rest[start..];
+        if !rest[1..].starts_with(|c: char| c.is_ascii_alphabetic() || c == '/' || c == '!') {
+            text.push('<');
+            rest = &rest[1..];
+            continue;
+        }
+        if rest.starts_with("<!--") {
+            rest = match rest.find("-->") {
+                Some(end) => &rest[end + 3..],
+                None => "",
+            };
+            continue;
+        }
+        let end = match rest.find('>') {
+            Some(v) => v,
+            None => {
+                rest = "";
+                break;
+            }
+        };
+        let tag = &rest[1..end];
+        rest = &rest[end + 1..];
+        let name = tag
+            .trim_start_matches('/')
+            .split(|c: char| c.is_whitespace() || c == '/')
+            .next()
+            .unwrap_or_default()
+            .to_ascii_lowercase();
+        if !tag.starts_with('/') && (name == "script" || name == "style") {
+            let close = format!("</{}", name);
+            rest = match rest.to_ascii_lowercase().find(&close) {
+                Some(v) => {
+                    let r = &rest[v..];
+                    match r.find('>') {
+                        Some(e) => &r[e + 1..],
+                        None => "",
+                    }
+                }
+                None => "",
+            };
+            continue;
+        }
+        if BLOCK_TAGS.contains(&name.as_str()) {
+            text.push(' ');
+        }
+    }
+    text.push_str(rest);
+    decode_html_entities(&text)
+        .split_whitespace()
+        .collect::<Vec<&str>>()
+        .join(" ")
+}
+
+fn decode_html_entity(name: &str) -> Option<char> {
+    match name {
+        "amp" => Some('&'),
+        "lt" => Some('<'),
+        "gt" => Some('>'),
+        "quot" => Some('"'),
+        "apos" => Some('\''),
+        "nbsp" => Some('\u{a0}'),
+        _ => {
+            let num = name.strip_prefix('#')?;
+            let code = match num.strip_prefix(['x', 'X']) {
+                Some(hex) => u32::from_str_radix(hex, 16).ok()?,
+                None => num.parse().ok()?,
+            };
+            char::from_u32(code)
+        }
+    }
+}
+
+pub fn decode_html_entities(text: &str) -> String {
+    let mut s = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(i) = rest.find('&') {
+        s.push_str(&rest[..i]);
+        rest = &rest[i + 1..];
+        let decoded = rest
+            .find(';')
+            .filter(|&end| end <= 10)
+            .and_then(|end| decode_html_entity(&rest[..end]).map(|c| (c, end)));
+        match decoded {
+            Some((c, end)) => {
+                s.push(c);
+                rest = &rest[end + 1..];
+            }
+            None => s.push('&'),
+        }
+    }
+    s.push_str(rest);
+    s
+}
+
+/// Percent-encode everything except RFC 3986 unreserved characters.
+pub fn percent_encode(text: &str) -> String {
+    let mut s = String::with_capacity(text.len());
+    for b in text.bytes() {
+        if b.is_ascii_alphanumeric() || b"-._~".contains(&b) {
+            s.push(b as char);
+        } else {
+            s.push_str(&format!("%{:02X}", b));
+        }
+    }
+    s
+}
+
+/// Current UTC time in RFC 3339, e.g. `2024-01-31T08:00:00Z`.
+pub fn rfc3339_now() -> String {
+    let secs = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|v| v.as_secs())
+        .unwrap_or_default();
+    let days = (secs / 86400) as i64;
+    let rem = secs % 86400;
+    // days to civil date, see http://howardhinnant.github.io/date_algorithms.html
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+        year,
+        month,
+        day,
+        rem / 3600,
+        rem % 3600 / 60,
+        rem % 60
+    )
+}
+
+/// Windows-1252 characters of bytes `0x80..=0x9F`, undefined bytes keep their C1 code point.
+const CP1252_HIGH: [char; 32] = [
+    '\u{20ac}', '\u{81}', '\u{201a}', '\u{192}', '\u{201e}', '\u{2026}', '\u{2020}', '\u{2021}',
+    '\u{2c6}', '\u{2030}', '\u{160}', '\u{2039}', '\u{152}', '\u{8d}', '\u{17d}', '\u{8f}',
+    '\u{90}', '\u{2018}', '\u{2019}', '\u{201c}', '\u{201d}', '\u{2022}', '\u{2013}', '\u{2014}',
+    '\u{2dc}', '\u{2122}', '\u{161}', '\u{203a}', '\u{153}', '\u{9d}', '\u{17e}', '\u{178}',
+];
+
+fn cp1252_byte(c: char) -> Option<u8> {
+    let code = c as u32;
+    if code < 0x80 || (0xa0..=0xff).contains(&code) {
+        return Some(code as u8);
+    }
+    CP1252_HIGH
+        .iter()
+        .position(|&v| v == c)
+        .map(|i| 0x80 + i as u8)
+}
+
+/// Undo UTF-8 text that was decoded as Windows-1252, e.g. `donâ€™t` -> `don’t`.
+/// Returns `None` if the text doesn't look like such mojibake.
+pub fn fix_mojibake(text: &str) -> Option<String> {
+    if text.is_ascii() {
+        return None;
+    }
+    let bytes: Option<Vec<u8>> = text.chars().map(cp1252_byte).collect();
+    let fixed = String::from_utf8(bytes?).ok()?;
+    if fixed == text {
+        None
+    } else {
+        Some(fixed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn strip_html_text() {
+        assert_eq!(strip_html("<p>a <b>bold</b>  word</p>"), "a bold word");
+        assert_eq!(strip_html("<div>one</div><div>two</div>"), "one two");
+        assert_eq!(strip_html("x<br>y"), "x y");
+        assert_eq!(strip_html("1 < 2 &amp; 3 &gt; 2"), "1 < 2 & 3 > 2");
+        assert_eq!(strip_html("&#65;&#x42;&unknown;"), "AB&unknown;");
+    }
+
+    #[test]
+    fn strip_html_skips_scripts_and_comments() {
+        assert_eq!(
+            strip_html("a<script>var x = '<b>';</script>b<!-- c -->d"),
+            "abd"
+        );
+        assert_eq!(strip_html("<STYLE>p { }</STYLE>text"), "text");
+        assert_eq!(strip_html("text<span"), "text");
+    }
+
+    #[test]
+    fn fix_mojibake_text() {
+        assert_eq!(fix_mojibake("donâ€™t").as_deref(), Some("don’t"));
+        assert_eq!(fix_mojibake("cafÃ©").as_deref(), Some("café"));
+        assert_eq!(fix_mojibake("ascii"), None);
+        // already correct UTF-8 whose Windows-1252 bytes aren't UTF-8
+        assert_eq!(fix_mojibake("café"), None);
+        assert_eq!(fix_mojibake("日本"), None);
+    }
+}
@@ -0,0 +1,195 @@
+use crate::utils::VOID_ELEMENTS;
+use beluga_core::beluga::*;
+use std::collections::HashSet;
+use std::future::Future;
+use std::panic::{self, AssertUnwindSafe};
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::thread;
+
+/// Elements whose end tags may be omitted.
+const OPTIONAL_END_ELEMENTS: [&str; 9] = ["p", "li", "dt", "dd", "tr", "td", "th", "option", "rt"];
+
+/// Lightweight tag balance check: every non-void element is closed, and in order.
+/// Omitted end tags of `p`, `li`, etc. are allowed. Content of `script` and `style` is skipped.
+pub fn is_well_formed_html(html: &str) -> bool {
+    let mut stack: Vec<String> = vec![];
+    let mut rest = html;
+    while let Some(start) = rest.find('<') {
+        rest = &rest[start..];
+        if rest.starts_with("<!--") {
+            match rest.find("-->") {
+                Some(end) => rest = &rest[end + 3..],
+                None => return false,
+            }
+            continue;
+        }
+        if !rest[1..].starts_with(|c: char| c.is_ascii_alphabetic() || c == '/' || c == '!') {
+            rest = &rest[1..];
+            continue;
+        }
+        let end = match rest.find('>') {
+            Some(v) => v,
+            None => return false,
+        };
+        let tag = &rest[1..end];
+        rest = &rest[end + 1..];
+        if tag.starts_with('!') {
+            continue;
+        }
+        let name = tag
+            .trim_start_matches('/')
+            .split(|c: char| c.is_whitespace() || c == '/')
+            .next()
+            .unwrap_or_default()
+            .to_ascii_lowercase();
+        if let Some(closing) = tag.strip_prefix('/') {
+            if VOID_ELEMENTS.contains(&name.as_str()) || closing.is_empty() {
+                continue;
+            }
+            loop {
+                match stack.pop() {
+                    Some(v) if v == name => break,
+                    Some(v) if OPTIONAL_END_ELEMENTS.contains(&v.as_str()) => {}
+                    _ => return false,
+                }
+            }
+        } else if !tag.ends_with('/') && !VOID_ELEMENTS.contains(&name.as_str()) {
+            if name == "script" || name == "style" {
+                let close = format!("</{}", name);
+                match rest.to_ascii_lowercase().find(&close) {
+                    Some(v) => rest = &rest[v..],
+                    None => return false,
+                }
+            }
+            stack.push(name);
+        }
+    }
+    stack
+        .iter()
+        .all(|v| OPTIONAL_END_ELEMENTS.contains(&v.as_str()))
+}
+
+#[derive(Default)]
+pub struct Report {
+    pub expected_entries: u64,
+    pub entries: u64,
+    pub empty_keys: u64,
+    pub invalid_utf8: Vec<String>,
+    pub malformed_html: Vec<String>,
+    pub tokens: u64,
+    /// Tokens referencing entries that don't exist
+    pub dangling_tokens: Vec<String>,
+    /// Opening or traversal stopped because the file couldn't be read
+    pub aborted: bool,
+}
+
+impl Report {
+    pub fn is_ok(&self) -> bool {
+        !self.aborted
+            && self.entries == self.expected_entries
+            && self.empty_keys == 0
+            && self.invalid_utf8.is_empty()
+            && self.malformed_html.is_empty()
+            && self.dangling_tokens.is_empty()
+    }
+}
+
+/// Future resolving to `Err` instead of unwinding when the wrapped one panics.
+struct CatchUnwind<F>(Pin<Box<F>>);
+
+impl<F: Future> Future for CatchUnwind<F> {
+    type Output = thread::Result<F::Output>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        match panic::catch_unwind(AssertUnwindSafe(|| self.0.as_mut().poll(cx))) {
+            Ok(Poll::Pending) => Poll::Pending,
+            Ok(Poll::Ready(v)) => Poll::Ready(Ok(v)),
+            Err(e) => Poll::Ready(Err(e)),
+        }
+    }
+}
+
+/// Traverse all entries and tokens of a Beluga dictionary and report inconsistencies.
+/// With `strict`, HTML of text entries must be well-formed, see `is_well_formed_html`.
+pub async fn validate(path: &str, strict: bool) -> Report {
+    let dict = match CatchUnwind(Box::pin(Beluga::from_file(path))).await {
+        Ok(v) => v,
+        Err(_) => {
+            return Report {
+                aborted: true,
+                ..Default::default()
+            }
+        }
+    };
+    let mut report = Report {
+        expected_entries: dict.metadata.entry_num,
+        ..Default::default()
+    };
+    let mut names: HashSet<String> = HashSet::new();
+    let traversed = panic::catch_unwind(AssertUnwindSafe(|| {
+        dict.traverse_entry(&mut |key: &EntryKey, value: &EntryValue| {
+            report.entries += 1;
+            if key.0.is_empty() {
+                report.empty_keys += 1;
+                return;
+            }
+            names.insert(key.0.clone());
+            if dict.file_type != BelFileType::Entry {
+                return;
+            }
+            match std::str::from_utf8(&value.0) {
+                Ok(html) => {
+                    if strict && !html.starts_with("@@@LINK=") && !is_well_formed_html(html) {
+                        report.malformed_html.push(key.0.clone());
+                    }
+                }
+                Err(_) => report.invalid_utf8.push(key.0.clone()),
+            }
+        });
+        dict.traverse_token(&mut |key: &EntryKey, value: &EntryValue| {
+            report.tokens += 1;
+            let entries = Beluga::parse_token_entries(&value.0);
+            if entries.iter().any(|v| !names.contains(v)) {
+                report.dangling_tokens.push(key.0.clone());
+            }
+        });
+    }));
+    report.aborted = traversed.is_err();
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn well_formed() {
+        assert!(is_well_formed_html(
+            "<div><p>a<br>b<img src=\"x\"/></p></div>"
+        ));
+        assert!(is_well_formed_html("<ul><li>one<li>two</ul>"));
+        assert!(is_well_formed_html("<p>open"));
+        assert!(is_well_formed_html("1 < 2 <!-- <div> -->"));
+        assert!(is_well_formed_html("<script>if (a <b) {}</script>"));
+        assert!(is_well_formed_html("<!DOCTYPE html><P>x</p>"));
+    }
+
+    #[test]
+    fn malformed() {
+        assert!(!is_well_formed_html("<div>"));
+        assert!(!is_well_formed_html("<b><i>x</b></i>"));
+        assert!(!is_well_formed_html("</div>"));
+        assert!(!is_well_formed_html("<div"));
+        assert!(!is_well_formed_html("<!-- open"));
+        assert!(!is_well_formed_html("<style>p {}"));
+    }
+
+    #[tokio::test]
+    async fn catch_unwind() {
+        assert_eq!(CatchUnwind(Box::pin(async { 1 })).await.unwrap(), 1);
+        assert!(CatchUnwind(Box::pin(async { panic!("corrupted") }))
+            .await
+            .is_err());
+    }
+}
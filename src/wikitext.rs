@@ -0,0 +1,202 @@
+use crate::utils::escape_html;
+
+/// Remove templates, comments, references and HTML tags (tag content is kept).
+fn strip_markup(text: &str) -> String {
+    let mut s = String::with_capacity(text.len());
+    let mut rest = text;
+    let mut depth = 0;
+    while !rest.is_empty() {
+        if let Some(r) = rest.strip_prefix("{{") {
+            depth += 1;
+            rest = r;
+        } else if depth > 0 && rest.starts_with("}}") {
+            depth -= 1;
+            rest = &rest[2..];
+        } else if rest.starts_with("<!--") {
+            rest = match rest.find("-->") {
+                Some(end) => &rest[end + 3..],
+                None => "",
+            };
+        } else if rest.starts_with("<ref") {
+            let end = rest.find('>').map(|v| v + 1).unwrap_or(rest.len());
+            rest = if rest[..end].ends_with("/>") {
+                &rest[end..]
+            } else {
+                match rest.find("</ref>") {
+                    Some(v) => &rest[v + 6..],
+                    None => "",
+                }
+            };
+        } else if rest.starts_with('<')
+            && rest[1..].starts_with(|c: char| c.is_ascii_alphabetic() || c == '/')
+        {
+            rest = match rest.find('>') {
+                Some(end) => &rest[end + 1..],
+                None => "",
+            };
+        } else {
+            let c = rest.chars().next().unwrap_or_default();
+            if depth == 0 {
+                s.push(c);
+            }
+            rest = &rest[c.len_utf8()..];
+        }
+    }
+    s
+}
+
+/// Bold, italic and internal links of a single line. Links to other namespaces are dropped.
+fn inline_to_html(line: &str) -> String {
+    let mut html = String::with_capacity(line.len());
+    let mut bold = false;
+    let mut italic = false;
+    let mut rest = line;
+    while let Some(i) = rest.find(['\'', '[']) {
+        html.push_str(&escape_html(&rest[..i]));
+        rest = &rest[i..];
+        if let Some(r) = rest.strip_prefix("'''") {
+            html.push_str(if bold { "</b>" } else { "<b>" });
+            bold = !bold;
+            rest = r;
+        } else if let Some(r) = rest.strip_prefix("''") {
+            html.push_str(if italic { "</i>" } else { "<i>" });
+            italic = !italic;
+            rest = r;
+        } else if let Some((link, r)) = rest
+            .strip_prefix("[[")
+            .and_then(|r| r.find("]]").map(|end| (&r[..end], &r[end + 2..])))
+        {
+            rest = r;
+            let (target, label) = match link.split_once('|') {
+                Some((target, label)) => (target, label),
+                None => (link, link.split('#').next().unwrap_or_default()),
+            };
+            let target = target.split('#').next().unwrap_or_default().trim();
+            if target.contains(':') {
+                continue;
+            }
+            if target.is_empty() {
+                html.push_str(&escape_html(label));
+            } else {
+                html.push_str(&format!(
+                    "<a href=\"entry://{}\">{}</a>",
+                    escape_html(target),
+                    escape_html(label)
+                ));
+            }
+        } else {
+            html.push_str(&escape_html(&rest[..1]));
+            rest = &rest[1..];
+        }
+    }
+    html.push_str(&escape_html(rest));
+    if italic {
+        html.push_str("</i>");
+    }
+    if bold {
+        html.push_str("</b>");
+    }
+    html
+}
+
+/// A minimal wikitext to HTML converter: headings, lists, paragraphs, bold, italic and links.
+/// Templates are removed because they can't be expanded without the wiki.
+pub fn to_html(text: &str) -> String {
+    let text = strip_markup(text);
+    let mut html = String::new();
+    let mut list: Option<&str> = None;
+    for line in text.lines() {
+        let line = line.trim_end();
+        let kind = match line.chars().next() {
+            Some('*') => Some("ul"),
+            Some('#') => Some("ol"),
+            Some(':') | Some(';') => Some("dl"),
+            _ => None,
+        };
+        if list != kind {
+            if let Some(tag) = list {
+                html.push_str(&format!("</{}>", tag));
+            }
+            if let Some(tag) = kind {
+                html.push_str(&format!("<{}>", tag));
+            }
+            list = kind;
+        }
+        if let Some(tag) = kind {
+            let depth = line
+                .chars()
+                .take_while(|c| ['*', '#', ':', ';'].contains(c))
+                .count();
+            let item = if tag == "dl" { "dd" } else { "li" };
+            html.push_str(&format!(
+                "<{} class=\"level-{}\">{}</{}>",
+                item,
+                depth,
+                inline_to_html(line[depth..].trim()),
+                item
+            ));
+            continue;
+        }
+        let level = line
+            .chars()
+            .take_while(|&c| c == '=')
+            .count()
+            .min(line.chars().rev().take_while(|&c| c == '=').count());
+        if level > 0 && line.len() > level * 2 {
+            let level = level.min(6);
+            html.push_str(&format!(
+                "<h{}>{}</h{}>",
+                level,
+                inline_to_html(line[level..line.len() - level].trim()),
+                level
+            ));
+        } else if !line.starts_with("----") {
+            let paragraph = inline_to_html(line.trim());
+            if !paragraph.trim().is_empty() {
+                html.push_str(&format!("<p>{}</p>", paragraph.trim()));
+            }
+        }
+    }
+    if let Some(tag) = list {
+        html.push_str(&format!("</{}>", tag));
+    }
+    html
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn headings_and_paragraphs() {
+        assert_eq!(
+            to_html("==English==\n===Noun===\n'''cat''' is ''small''\n----\n"),
+            "<h2>English</h2><h3>Noun</h3><p><b>cat</b> is <i>small</i></p>"
+        );
+    }
+
+    #[test]
+    fn lists() {
+        assert_eq!(
+            to_html("# one\n## two\n* dot\n: indent"),
+            "<ol><li class=\"level-1\">one</li><li class=\"level-2\">two</li></ol>\
+            <ul><li class=\"level-1\">dot</li></ul><dl><dd class=\"level-1\">indent</dd></dl>"
+        );
+    }
+
+    #[test]
+    fn links() {
+        assert_eq!(
+            to_html("[[dog]], [[dog#Noun|dogs]], [[Category:Animals]], [[#top|here]]"),
+            "<p><a href=\"entry://dog\">dog</a>, <a href=\"entry://dog\">dogs</a>, , here</p>"
+        );
+    }
+
+    #[test]
+    fn markup_is_removed() {
+        assert_eq!(
+            to_html("{{en-noun|{{nested}}}}a<ref>note</ref>b<ref name=\"x\"/><!-- c --><span>d</span> < 2"),
+            "<p>abd &lt; 2</p>"
+        );
+    }
+}